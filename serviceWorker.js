@@ -38,3 +38,14 @@ self.addEventListener('fetch', (event) => {
         })
     );
 });
+
+/* Wake open clients so they flush the note outbox once connectivity returns */
+self.addEventListener('sync', (event) => {
+    if (event.tag === 'minions-outbox') {
+        event.waitUntil(
+            self.clients.matchAll({ type: 'window', includeUncontrolled: true }).then((clients) => {
+                clients.forEach((client) => client.postMessage({ type: 'minions-outbox-flush' }));
+            })
+        );
+    }
+});
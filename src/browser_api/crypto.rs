@@ -2,7 +2,10 @@ use js_sys::{ArrayBuffer, Object, Uint8Array};
 use nostro2::userkeys::UserKeys;
 use wasm_bindgen::{JsCast, JsValue};
 use wasm_bindgen_futures::JsFuture;
-use web_sys::{AesKeyGenParams, CryptoKey, SubtleCrypto};
+use web_sys::{
+    AesDerivedKeyParams, AesGcmParams, AesKeyGenParams, CryptoKey, HkdfParams, Pbkdf2Params,
+    SubtleCrypto,
+};
 
 fn crypto_subtle() -> Result<SubtleCrypto, JsValue> {
     let window = web_sys::window().ok_or(JsValue::from_str("No window available"))?;
@@ -46,3 +49,99 @@ pub async fn crypto_to_user_keys(
         false => Ok(UserKeys::new(&key_hex).unwrap()),
     }
 }
+
+pub async fn derive_storage_key(user_keys: &UserKeys, info: &str) -> Result<CryptoKey, JsValue> {
+    let crypto = crypto_subtle()?;
+    let key_object = user_keys_to_object(user_keys);
+    let base_usages: js_sys::Array = [JsValue::from_str("deriveKey")].iter().collect();
+    let base_key = crypto.import_key_with_str("raw", &key_object, "HKDF", false, &base_usages)?;
+    let base_key: CryptoKey = JsFuture::from(base_key).await?.dyn_into()?;
+    let params = HkdfParams::new(
+        "HKDF",
+        &JsValue::from_str("SHA-256"),
+        &Uint8Array::from(info.as_bytes()),
+        &Uint8Array::new_with_length(32),
+    );
+    let derived = AesDerivedKeyParams::new("AES-GCM", 256);
+    let usages: js_sys::Array = [JsValue::from_str("encrypt"), JsValue::from_str("decrypt")]
+        .iter()
+        .collect();
+    let key =
+        crypto.derive_key_with_object_and_object(&params, &base_key, &derived, false, &usages)?;
+    JsFuture::from(key).await?.dyn_into()
+}
+
+pub async fn aes_gcm_encrypt(
+    key: &CryptoKey,
+    plaintext: &[u8],
+    additional_data: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>), JsValue> {
+    let iv = random_bytes(12)?;
+    let params = AesGcmParams::new("AES-GCM", &Uint8Array::from(&iv[..]));
+    params.set_additional_data(&Uint8Array::from(additional_data));
+    let ciphertext = crypto_subtle()?.encrypt_with_object_and_u8_array(&params, key, plaintext)?;
+    let ciphertext: ArrayBuffer = JsFuture::from(ciphertext).await?.dyn_into()?;
+    Ok((iv, Uint8Array::new(&ciphertext).to_vec()))
+}
+
+pub async fn aes_gcm_decrypt(
+    key: &CryptoKey,
+    iv: &[u8],
+    ciphertext: &[u8],
+    additional_data: &[u8],
+) -> Result<Vec<u8>, JsValue> {
+    let params = AesGcmParams::new("AES-GCM", &Uint8Array::from(iv));
+    params.set_additional_data(&Uint8Array::from(additional_data));
+    let plaintext = crypto_subtle()?.decrypt_with_object_and_u8_array(&params, key, ciphertext)?;
+    let plaintext: ArrayBuffer = JsFuture::from(plaintext).await?.dyn_into()?;
+    Ok(Uint8Array::new(&plaintext).to_vec())
+}
+
+pub fn random_bytes(length: usize) -> Result<Vec<u8>, JsValue> {
+    let window = web_sys::window().ok_or(JsValue::from_str("No window available"))?;
+    let mut bytes = vec![0u8; length];
+    window
+        .crypto()?
+        .get_random_values_with_u8_array(&mut bytes)?;
+    Ok(bytes)
+}
+
+pub async fn derive_passphrase_key(
+    passphrase: &str,
+    salt: &[u8],
+    iterations: u32,
+) -> Result<CryptoKey, JsValue> {
+    let crypto = crypto_subtle()?;
+    let passphrase = Uint8Array::from(passphrase.as_bytes());
+    let base_usages: js_sys::Array = [JsValue::from_str("deriveKey")].iter().collect();
+    let base_key = crypto.import_key_with_str("raw", &passphrase, "PBKDF2", false, &base_usages)?;
+    let base_key: CryptoKey = JsFuture::from(base_key).await?.dyn_into()?;
+    let params = Pbkdf2Params::new(
+        "PBKDF2",
+        &JsValue::from_str("SHA-256"),
+        iterations,
+        &Uint8Array::from(salt),
+    );
+    let derived = AesDerivedKeyParams::new("AES-GCM", 256);
+    let usages: js_sys::Array = [JsValue::from_str("encrypt"), JsValue::from_str("decrypt")]
+        .iter()
+        .collect();
+    let key =
+        crypto.derive_key_with_object_and_object(&params, &base_key, &derived, false, &usages)?;
+    JsFuture::from(key).await?.dyn_into()
+}
+
+pub async fn export_jwk(key: &CryptoKey) -> Result<JsValue, JsValue> {
+    JsFuture::from(crypto_subtle()?.export_key("jwk", key)?).await
+}
+
+pub async fn import_jwk(
+    jwk: &Object,
+    algorithm: &Object,
+    extractable: bool,
+    usages: &js_sys::Array,
+) -> Result<CryptoKey, JsValue> {
+    let key =
+        crypto_subtle()?.import_key_with_object("jwk", jwk, algorithm, extractable, usages)?;
+    JsFuture::from(key).await?.dyn_into()
+}
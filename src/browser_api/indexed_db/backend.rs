@@ -0,0 +1,263 @@
+use std::marker::PhantomData;
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use wasm_bindgen::JsValue;
+use yew::platform::pinned::oneshot::Receiver;
+
+use super::{
+    batch::{track_batch, IdbBatchReport},
+    cache::{record_access, record_delete, record_write, spawn_enforce},
+    map_receiver,
+    observer::{notify_change, IdbChange},
+    put_record,
+    query::{IdbKey, IdbQuery},
+    request_result, require_plaintext, spawn_with_store, IdbError, IdbResult, IdbStoreManager,
+};
+
+pub trait IdbBackend {
+    // Backends that seal records may hold stores whose models are `encrypted()`.
+    const ENCRYPTS: bool = false;
+    fn put<M: IdbStoreManager>(
+        &self,
+        key: IdbKey,
+        value: Value,
+    ) -> IdbResult<Receiver<IdbResult<()>>>;
+    fn put_many<M: IdbStoreManager>(
+        &self,
+        records: Vec<(IdbKey, Value)>,
+    ) -> IdbResult<Receiver<IdbResult<IdbBatchReport>>>;
+    fn get<M: IdbStoreManager>(&self, key: IdbKey)
+        -> IdbResult<Receiver<IdbResult<Option<Value>>>>;
+    fn delete<M: IdbStoreManager>(&self, key: IdbKey) -> IdbResult<Receiver<IdbResult<()>>>;
+    fn delete_many<M: IdbStoreManager>(
+        &self,
+        keys: Vec<IdbKey>,
+    ) -> IdbResult<Receiver<IdbResult<IdbBatchReport>>>;
+    fn clear<M: IdbStoreManager>(&self) -> IdbResult<Receiver<IdbResult<()>>>;
+    fn query<M: IdbStoreManager>(
+        &self,
+        query: IdbQuery,
+    ) -> IdbResult<Receiver<IdbResult<Vec<Value>>>>;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IndexedDbBackend;
+
+pub(super) fn to_js(value: &Value) -> IdbResult<JsValue> {
+    Ok(value.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+}
+
+pub(super) fn from_js(value: JsValue) -> IdbResult<Value> {
+    Ok(serde_wasm_bindgen::from_value(value)?)
+}
+
+impl IdbBackend for IndexedDbBackend {
+    fn put<M: IdbStoreManager>(
+        &self,
+        key: IdbKey,
+        value: Value,
+    ) -> IdbResult<Receiver<IdbResult<()>>> {
+        let object_store_request = M::request_store_open()?;
+        let value = to_js(&value)?;
+        let inline_key = M::store_schema().key_path.is_some();
+        let cached = M::cache_policy().is_some();
+        let (db_name, store_name) = (M::db_name(), M::store_name());
+        Ok(spawn_with_store(object_store_request, move |store| {
+            let request = put_record(&store, inline_key, &value, &key.to_js())?;
+            if cached {
+                record_write(&store, &key, &value)?;
+            }
+            Ok(map_receiver(request_result(&request), move |_| {
+                notify_change(db_name, store_name, IdbChange::Put(vec![key]));
+                spawn_enforce::<M>();
+                Ok(())
+            }))
+        }))
+    }
+    fn put_many<M: IdbStoreManager>(
+        &self,
+        records: Vec<(IdbKey, Value)>,
+    ) -> IdbResult<Receiver<IdbResult<IdbBatchReport>>> {
+        let object_store_request = M::request_store_open()?;
+        let (keys, values): (Vec<IdbKey>, Vec<IdbResult<JsValue>>) = records
+            .into_iter()
+            .map(|(key, value)| (key, to_js(&value)))
+            .unzip();
+        let inline_key = M::store_schema().key_path.is_some();
+        let cached = M::cache_policy().is_some();
+        let (db_name, store_name) = (M::db_name(), M::store_name());
+        Ok(spawn_with_store(object_store_request, move |store| {
+            let requests = values
+                .into_iter()
+                .zip(keys.iter())
+                .map(|(value, key)| {
+                    let value = value?;
+                    let request = put_record(&store, inline_key, &value, &key.to_js())?;
+                    if cached {
+                        record_write(&store, key, &value)?;
+                    }
+                    Ok(request)
+                })
+                .collect();
+            let report = track_batch(&store.transaction(), requests);
+            Ok(map_receiver(report, move |report| {
+                let saved = report.succeeded_keys(keys);
+                notify_change(db_name, store_name, IdbChange::Put(saved));
+                spawn_enforce::<M>();
+                Ok(report)
+            }))
+        }))
+    }
+    fn get<M: IdbStoreManager>(
+        &self,
+        key: IdbKey,
+    ) -> IdbResult<Receiver<IdbResult<Option<Value>>>> {
+        let object_store_request = M::request_store_open()?;
+        let policy = M::cache_policy();
+        Ok(spawn_with_store(object_store_request, move |store| {
+            let request = store.get(&key.to_js())?;
+            let expired = match policy {
+                Some(policy) => Some(record_access(&store, &key, policy)?),
+                None => None,
+            };
+            Ok(map_receiver(request_result(&request), move |result| {
+                let expired = expired.is_some_and(|expired| expired.get());
+                if result.is_null() || result.is_undefined() || expired {
+                    return Ok(None);
+                }
+                from_js(result).map(Some)
+            }))
+        }))
+    }
+    fn delete<M: IdbStoreManager>(&self, key: IdbKey) -> IdbResult<Receiver<IdbResult<()>>> {
+        let object_store_request = M::request_store_open()?;
+        let cached = M::cache_policy().is_some();
+        let (db_name, store_name) = (M::db_name(), M::store_name());
+        Ok(spawn_with_store(object_store_request, move |store| {
+            let request = store.delete(&key.to_js())?;
+            if cached {
+                record_delete(&store, &key)?;
+            }
+            Ok(map_receiver(request_result(&request), move |_| {
+                notify_change(db_name, store_name, IdbChange::Delete(vec![key]));
+                Ok(())
+            }))
+        }))
+    }
+    fn delete_many<M: IdbStoreManager>(
+        &self,
+        keys: Vec<IdbKey>,
+    ) -> IdbResult<Receiver<IdbResult<IdbBatchReport>>> {
+        let object_store_request = M::request_store_open()?;
+        let cached = M::cache_policy().is_some();
+        let (db_name, store_name) = (M::db_name(), M::store_name());
+        Ok(spawn_with_store(object_store_request, move |store| {
+            let requests = keys
+                .iter()
+                .map(|key| {
+                    let request = store.delete(&key.to_js())?;
+                    if cached {
+                        record_delete(&store, key)?;
+                    }
+                    Ok(request)
+                })
+                .collect();
+            let report = track_batch(&store.transaction(), requests);
+            Ok(map_receiver(report, move |report| {
+                let deleted = report.succeeded_keys(keys);
+                notify_change(db_name, store_name, IdbChange::Delete(deleted));
+                Ok(report)
+            }))
+        }))
+    }
+    fn clear<M: IdbStoreManager>(&self) -> IdbResult<Receiver<IdbResult<()>>> {
+        M::clear_store()
+    }
+    fn query<M: IdbStoreManager>(
+        &self,
+        query: IdbQuery,
+    ) -> IdbResult<Receiver<IdbResult<Vec<Value>>>> {
+        let object_store_request = M::request_read_open()?;
+        Ok(spawn_with_store(object_store_request, move |store| {
+            let values = query.collect(&store)?;
+            Ok(map_receiver(values, |values| {
+                values.into_iter().map(from_js).collect()
+            }))
+        }))
+    }
+}
+
+pub struct IdbStore<M, B = IndexedDbBackend> {
+    backend: B,
+    _record: PhantomData<M>,
+}
+impl<M, B> IdbStore<M, B>
+where
+    M: IdbStoreManager + Serialize + DeserializeOwned,
+    B: IdbBackend,
+{
+    pub fn new(backend: B) -> Self {
+        Self {
+            backend,
+            _record: PhantomData,
+        }
+    }
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+    fn records(&self) -> IdbResult<&B> {
+        if !B::ENCRYPTS {
+            require_plaintext::<M>()?;
+        }
+        Ok(&self.backend)
+    }
+    pub async fn save(&self, record: &M) -> IdbResult<()> {
+        let value = serde_json::to_value(record)?;
+        self.records()?
+            .put::<M>(record.document_key(), value)?
+            .await?
+    }
+    pub async fn save_many(&self, records: &[M]) -> IdbResult<IdbBatchReport> {
+        let records = records
+            .iter()
+            .map(|record| Ok((record.document_key(), serde_json::to_value(record)?)))
+            .collect::<IdbResult<Vec<_>>>()?;
+        self.records()?.put_many::<M>(records)?.await?
+    }
+    pub async fn get(&self, key: impl Into<IdbKey>) -> IdbResult<Option<M>> {
+        match self.records()?.get::<M>(key.into())?.await?? {
+            Some(value) => Ok(Some(serde_json::from_value(value)?)),
+            None => Ok(None),
+        }
+    }
+    pub async fn delete(&self, record: &M) -> IdbResult<()> {
+        self.backend.delete::<M>(record.document_key())?.await?
+    }
+    pub async fn delete_many(&self, records: &[M]) -> IdbResult<IdbBatchReport> {
+        let keys = records.iter().map(|record| record.document_key()).collect();
+        self.backend.delete_many::<M>(keys)?.await?
+    }
+    pub async fn clear(&self) -> IdbResult<()> {
+        self.backend.clear::<M>()?.await?
+    }
+    pub async fn all(&self) -> IdbResult<Vec<M>> {
+        self.query(IdbQuery::new()).await
+    }
+    pub async fn query(&self, query: IdbQuery) -> IdbResult<Vec<M>> {
+        self.records()?
+            .query::<M>(query)?
+            .await??
+            .into_iter()
+            .map(|value| serde_json::from_value(value).map_err(IdbError::from))
+            .collect()
+    }
+}
+impl<M> Default for IdbStore<M, IndexedDbBackend> {
+    fn default() -> Self {
+        Self {
+            backend: IndexedDbBackend,
+            _record: PhantomData,
+        }
+    }
+}
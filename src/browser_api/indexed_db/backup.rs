@@ -0,0 +1,336 @@
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use js_sys::{Array, Object};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{CryptoKey, IdbTransactionMode};
+
+use super::{
+    await_transaction,
+    backend::{from_js, to_js},
+    batch::track_batch,
+    connection::open_transaction,
+    observer::{notify_change, IdbChange},
+    query::IdbKey,
+    schema::IdbSchema,
+    IdbError, IdbResult,
+};
+use crate::browser_api::crypto::{
+    aes_gcm_decrypt, aes_gcm_encrypt, derive_passphrase_key, export_jwk, import_jwk, random_bytes,
+};
+
+pub const BACKUP_FORMAT: u32 = 1;
+const PASSPHRASE_ITERATIONS: u32 = 600_000;
+const CRYPTO_KEY_TAG: &str = "$crypto_key";
+
+pub type IdbBackupStores = BTreeMap<String, Vec<IdbBackupRecord>>;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IdbBackupRecord {
+    pub key: IdbKey,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum IdbBackupPayload {
+    Plain {
+        stores: IdbBackupStores,
+    },
+    Encrypted {
+        salt: String,
+        iterations: u32,
+        iv: String,
+        data: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdbConflictPolicy {
+    #[default]
+    KeepLocal,
+    Overwrite,
+    Replace,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IdbRestoreReport {
+    pub restored: usize,
+    pub skipped: usize,
+    pub failed: Vec<(String, IdbKey, IdbError)>,
+    pub unknown_stores: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IdbBackup {
+    pub format: u32,
+    pub db_name: String,
+    pub db_version: u32,
+    pub created_at: f64,
+    pub payload: IdbBackupPayload,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped: Vec<(String, IdbKey)>,
+}
+impl IdbBackup {
+    pub async fn export<S: IdbSchema>(passphrase: Option<&str>) -> IdbResult<Self> {
+        let (stores, skipped) = read_stores::<S>(passphrase.is_some()).await?;
+        let payload = match passphrase {
+            Some(passphrase) => seal(S::db_name(), passphrase, &stores).await?,
+            None => IdbBackupPayload::Plain { stores },
+        };
+        Ok(Self {
+            format: BACKUP_FORMAT,
+            db_name: S::db_name().to_string(),
+            db_version: S::db_version(),
+            created_at: js_sys::Date::now(),
+            payload,
+            skipped,
+        })
+    }
+    pub fn to_json(&self) -> IdbResult<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+    pub fn from_json(json: &str) -> IdbResult<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+    pub fn is_encrypted(&self) -> bool {
+        matches!(self.payload, IdbBackupPayload::Encrypted { .. })
+    }
+    pub async fn restore<S: IdbSchema>(
+        &self,
+        passphrase: Option<&str>,
+        policy: IdbConflictPolicy,
+    ) -> IdbResult<IdbRestoreReport> {
+        if self.format > BACKUP_FORMAT {
+            return Err(IdbError::VersionError(format!(
+                "Unsupported backup format {}",
+                self.format
+            )));
+        }
+        if self.db_name != S::db_name() {
+            return Err(IdbError::VersionError(format!(
+                "Backup is for database {}, not {}",
+                self.db_name,
+                S::db_name()
+            )));
+        }
+        if self.db_version > S::db_version() {
+            return Err(IdbError::VersionError(format!(
+                "Backup was made with schema version {}, this build has {}",
+                self.db_version,
+                S::db_version()
+            )));
+        }
+        let stores = match &self.payload {
+            IdbBackupPayload::Plain { stores } => stores.clone(),
+            IdbBackupPayload::Encrypted { .. } => {
+                let passphrase = passphrase.ok_or(IdbError::Decryption(
+                    "Backup requires a passphrase".to_string(),
+                ))?;
+                unseal(&self.db_name, passphrase, &self.payload).await?
+            }
+        };
+        write_stores::<S>(stores, policy).await
+    }
+}
+
+fn associated_data(db_name: &str) -> Vec<u8> {
+    format!("minions-backup/{}", db_name).into_bytes()
+}
+
+async fn seal(
+    db_name: &str,
+    passphrase: &str,
+    stores: &IdbBackupStores,
+) -> IdbResult<IdbBackupPayload> {
+    let salt = random_bytes(16)?;
+    let key = derive_passphrase_key(passphrase, &salt, PASSPHRASE_ITERATIONS).await?;
+    let plaintext = serde_json::to_vec(stores)?;
+    let (iv, data) = aes_gcm_encrypt(&key, &plaintext, &associated_data(db_name)).await?;
+    Ok(IdbBackupPayload::Encrypted {
+        salt: STANDARD.encode(salt),
+        iterations: PASSPHRASE_ITERATIONS,
+        iv: STANDARD.encode(iv),
+        data: STANDARD.encode(data),
+    })
+}
+
+async fn unseal(
+    db_name: &str,
+    passphrase: &str,
+    payload: &IdbBackupPayload,
+) -> IdbResult<IdbBackupStores> {
+    let IdbBackupPayload::Encrypted {
+        salt,
+        iterations,
+        iv,
+        data,
+    } = payload
+    else {
+        return Err(IdbError::Decryption("Backup is not encrypted".to_string()));
+    };
+    let decode = |field: &str| {
+        STANDARD
+            .decode(field)
+            .map_err(|e| IdbError::Decryption(e.to_string()))
+    };
+    let key = derive_passphrase_key(passphrase, &decode(salt)?, *iterations).await?;
+    let plaintext = aes_gcm_decrypt(
+        &key,
+        &decode(iv)?,
+        &decode(data)?,
+        &associated_data(db_name),
+    )
+    .await
+    .map_err(|_| IdbError::Decryption("Wrong passphrase or corrupted backup".to_string()))?;
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+// CryptoKeys are structured-cloned by IndexedDB but have no JSON form, so extractable
+// keys are carried as JWK alongside what is needed to import them again. Key material
+// only leaves inside an encrypted backup; other keys are skipped and reported.
+async fn to_backup_value(value: JsValue, sealed: bool) -> IdbResult<Option<Value>> {
+    let key = match value.dyn_into::<CryptoKey>() {
+        Ok(key) => key,
+        Err(value) => return from_js(value).map(Some),
+    };
+    if !sealed || !key.extractable() {
+        return Ok(None);
+    }
+    let mut fields = Map::new();
+    fields.insert("jwk".to_string(), from_js(export_jwk(&key).await?)?);
+    fields.insert("algorithm".to_string(), from_js(key.algorithm()?.into())?);
+    fields.insert("usages".to_string(), from_js(key.usages().into())?);
+    fields.insert("extractable".to_string(), Value::Bool(key.extractable()));
+    let mut tagged = Map::new();
+    tagged.insert(CRYPTO_KEY_TAG.to_string(), Value::Object(fields));
+    Ok(Some(Value::Object(tagged)))
+}
+
+async fn from_backup_value(value: &Value) -> IdbResult<JsValue> {
+    let key = match value.as_object() {
+        Some(object) if object.len() == 1 => object.get(CRYPTO_KEY_TAG),
+        _ => None,
+    };
+    let Some(key) = key else {
+        return to_js(value);
+    };
+    let jwk: Object = to_js(&key["jwk"])?.unchecked_into();
+    let algorithm: Object = to_js(&key["algorithm"])?.unchecked_into();
+    let usages: Array = to_js(&key["usages"])?.unchecked_into();
+    let extractable = key["extractable"].as_bool().unwrap_or(false);
+    Ok(import_jwk(&jwk, &algorithm, extractable, &usages)
+        .await?
+        .into())
+}
+
+async fn read_stores<S: IdbSchema>(
+    sealed: bool,
+) -> IdbResult<(IdbBackupStores, Vec<(String, IdbKey)>)> {
+    let names: Vec<&'static str> = S::store_schemas().into_keys().collect();
+    let mut stores = IdbBackupStores::new();
+    let mut skipped = Vec::new();
+    if names.is_empty() {
+        return Ok((stores, skipped));
+    }
+    let transaction = open_transaction::<S>(&names, IdbTransactionMode::Readonly).await?;
+    let requests = names
+        .iter()
+        .map(|name| {
+            let store = transaction.object_store(name)?;
+            Ok((*name, store.get_all_keys()?, store.get_all()?))
+        })
+        .collect::<IdbResult<Vec<_>>>()?;
+    await_transaction(&transaction, Rc::new(RefCell::new(()))).await??;
+    for (name, keys, values) in requests {
+        let keys: Array = keys.result()?.unchecked_into();
+        let values: Array = values.result()?.unchecked_into();
+        let mut records = Vec::new();
+        for (key, value) in keys.iter().zip(values.iter()) {
+            let key = IdbKey::from_js(&key)?;
+            match to_backup_value(value, sealed).await? {
+                Some(value) => records.push(IdbBackupRecord { key, value }),
+                None => skipped.push((name.to_string(), key)),
+            }
+        }
+        stores.insert(name.to_string(), records);
+    }
+    Ok((stores, skipped))
+}
+
+// KeepLocal relies on `add` failing with a ConstraintError for keys that already exist.
+async fn write_stores<S: IdbSchema>(
+    stores: IdbBackupStores,
+    policy: IdbConflictPolicy,
+) -> IdbResult<IdbRestoreReport> {
+    let schemas = S::store_schemas();
+    let mut report = IdbRestoreReport::default();
+    let mut touched: Vec<&'static str> = Vec::new();
+    let mut records = Vec::new();
+    for (name, entries) in stores {
+        let Some(schema) = schemas.get(name.as_str()) else {
+            report.unknown_stores.push(name);
+            continue;
+        };
+        touched.push(schema.name);
+        for record in entries {
+            let value = from_backup_value(&record.value).await?;
+            records.push((schema.name, schema.key_path.is_some(), record.key, value));
+        }
+    }
+    if touched.is_empty() {
+        return Ok(report);
+    }
+    let transaction = open_transaction::<S>(&touched, IdbTransactionMode::Readwrite).await?;
+    if policy == IdbConflictPolicy::Replace {
+        for name in &touched {
+            transaction.object_store(name)?.clear()?;
+        }
+    }
+    let requests = records
+        .iter()
+        .map(|(name, inline_key, key, value)| {
+            let store = transaction.object_store(name)?;
+            let request = match (policy, inline_key) {
+                (IdbConflictPolicy::KeepLocal, true) => store.add(value)?,
+                (IdbConflictPolicy::KeepLocal, false) => store.add_with_key(value, &key.to_js())?,
+                (_, true) => store.put(value)?,
+                (_, false) => store.put_with_key(value, &key.to_js())?,
+            };
+            Ok(request)
+        })
+        .collect();
+    let batch = track_batch(&transaction, requests).await??;
+    report.restored = batch.succeeded;
+    for (index, error) in &batch.failed {
+        let (name, _, key, _) = &records[*index];
+        match (policy, error) {
+            (IdbConflictPolicy::KeepLocal, IdbError::Constraint(_)) => report.skipped += 1,
+            _ => report
+                .failed
+                .push((name.to_string(), key.clone(), error.clone())),
+        }
+    }
+    let restored = batch.succeeded_keys(
+        records
+            .into_iter()
+            .map(|(name, _, key, _)| (name, key))
+            .collect(),
+    );
+    for name in touched {
+        if policy == IdbConflictPolicy::Replace {
+            notify_change(S::db_name(), name, IdbChange::Clear);
+        }
+        let keys: Vec<IdbKey> = restored
+            .iter()
+            .filter(|(store, _)| *store == name)
+            .map(|(_, key)| key.clone())
+            .collect();
+        if !keys.is_empty() {
+            notify_change(S::db_name(), name, IdbChange::Put(keys));
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,83 @@
+use std::{cell::RefCell, rc::Rc};
+
+use wasm_bindgen::{closure::Closure, JsCast};
+use web_sys::{IdbRequest, IdbTransaction};
+use yew::platform::{
+    pinned::oneshot::{self, Receiver},
+    spawn_local,
+};
+
+use super::{await_transaction, IdbError, IdbResult};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IdbBatchReport {
+    pub succeeded: usize,
+    pub failed: Vec<(usize, IdbError)>,
+}
+impl IdbBatchReport {
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+    pub fn succeeded_keys<K>(&self, keys: Vec<K>) -> Vec<K> {
+        keys.into_iter()
+            .enumerate()
+            .filter(|(index, _)| !self.failed.iter().any(|(failed, _)| failed == index))
+            .map(|(_, key)| key)
+            .collect()
+    }
+}
+
+// Failed requests are recorded and their error events cancelled, so one bad record
+// does not abort the rest of the batch.
+pub(super) fn track_batch(
+    transaction: &IdbTransaction,
+    requests: Vec<IdbResult<IdbRequest>>,
+) -> Receiver<IdbResult<IdbBatchReport>> {
+    let report = Rc::new(RefCell::new(IdbBatchReport::default()));
+    let mut pending = Vec::new();
+    for (index, request) in requests.into_iter().enumerate() {
+        match request {
+            Ok(request) => pending.push((index, request)),
+            Err(error) => report.borrow_mut().failed.push((index, error)),
+        }
+    }
+    let pending = Rc::new(pending);
+    let success_report = report.clone();
+    let on_success = Closure::<dyn FnMut(web_sys::Event)>::new(move |_: web_sys::Event| {
+        success_report.borrow_mut().succeeded += 1;
+    });
+    let error_report = report.clone();
+    let error_requests = pending.clone();
+    let on_error = Closure::<dyn FnMut(web_sys::Event)>::new(move |event: web_sys::Event| {
+        event.prevent_default();
+        event.stop_propagation();
+        let Some(target) = event.target() else {
+            return;
+        };
+        if let Some((index, request)) = error_requests.iter().find(|(_, request)| {
+            let request: &web_sys::EventTarget = request;
+            request == &target
+        }) {
+            let error = IdbError::from_request(request);
+            error_report.borrow_mut().failed.push((*index, error));
+        }
+    });
+    for (_, request) in pending.iter() {
+        request.set_onsuccess(Some(on_success.as_ref().unchecked_ref()));
+        request.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+    }
+    let completion = await_transaction(transaction, report);
+    let (sender, receiver) = oneshot::channel();
+    spawn_local(async move {
+        let result = completion
+            .await
+            .map_err(IdbError::from)
+            .and_then(|report| report);
+        drop((on_success, on_error));
+        let _ = sender.send(result.map(|mut report| {
+            report.failed.sort_by_key(|(index, _)| *index);
+            report
+        }));
+    });
+    receiver
+}
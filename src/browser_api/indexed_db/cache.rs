@@ -0,0 +1,339 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashSet,
+    rc::Rc,
+    time::Duration,
+};
+
+use gloo::console::warn;
+use gloo_timers::future::sleep;
+use js_sys::Date;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{IdbObjectStore, IdbTransactionMode, StorageManager};
+
+use super::{
+    await_transaction,
+    connection::open_transaction,
+    observer::{notify_change, IdbChange},
+    query::{IdbKey, IdbQuery, IdbRange},
+    request_result,
+    schema::IdbStoreSchema,
+    IdbError, IdbResult, IdbStoreManager,
+};
+
+// Bookkeeping for every store with a cache policy lives in one shared store, which
+// has to be registered in the schema migrations alongside the cached stores.
+pub const CACHE_STORE: &str = "minions_cache";
+
+const ENFORCE_DELAY: Duration = Duration::from_secs(2);
+const ENFORCE_SLACK: f64 = 0.1;
+
+thread_local! {
+    static SCHEDULED: RefCell<HashSet<(&'static str, &'static str)>> = RefCell::new(HashSet::new());
+}
+
+pub fn cache_store_schema() -> IdbStoreSchema {
+    IdbStoreSchema::new(CACHE_STORE)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IdbCacheEntry {
+    pub store: String,
+    pub key: IdbKey,
+    pub written_at: f64,
+    pub accessed_at: f64,
+    pub bytes: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct IdbCachePolicy {
+    pub ttl: Option<Duration>,
+    pub max_entries: Option<usize>,
+    pub max_bytes: Option<usize>,
+}
+impl IdbCachePolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+    pub fn is_expired(&self, entry: &IdbCacheEntry, now: f64) -> bool {
+        self.ttl
+            .is_some_and(|ttl| entry.written_at + ttl.as_millis() as f64 <= now)
+    }
+    // Expired entries go first, then the least recently used until both limits hold.
+    pub fn victims(&self, entries: &[IdbCacheEntry], now: f64) -> Vec<IdbKey> {
+        let mut entries: Vec<&IdbCacheEntry> = entries.iter().collect();
+        entries.sort_by(|a, b| a.accessed_at.total_cmp(&b.accessed_at));
+        let (expired, live): (Vec<_>, Vec<_>) = entries
+            .into_iter()
+            .partition(|entry| self.is_expired(entry, now));
+        let mut victims: Vec<IdbKey> = expired.into_iter().map(|entry| entry.key.clone()).collect();
+        let mut count = live.len();
+        let mut bytes: usize = live.iter().map(|entry| entry.bytes as usize).sum();
+        for entry in live {
+            let over_entries = self.max_entries.is_some_and(|max| count > max);
+            let over_bytes = self.max_bytes.is_some_and(|max| bytes > max);
+            if !over_entries && !over_bytes {
+                break;
+            }
+            count -= 1;
+            bytes -= entry.bytes as usize;
+            victims.push(entry.key.clone());
+        }
+        victims
+    }
+}
+
+fn entry_key(store_name: &str, key: &IdbKey) -> JsValue {
+    IdbKey::Array(vec![IdbKey::from(store_name), key.clone()]).to_js()
+}
+
+fn store_range(store_name: &str) -> IdbRange {
+    IdbRange::prefix(vec![IdbKey::from(store_name)])
+}
+
+fn cache_store(store: &IdbObjectStore) -> IdbResult<IdbObjectStore> {
+    store
+        .transaction()
+        .object_store(CACHE_STORE)
+        .map_err(|_| IdbError::StoreMissing(CACHE_STORE.to_string()))
+}
+
+pub(super) fn record_write(store: &IdbObjectStore, key: &IdbKey, value: &JsValue) -> IdbResult<()> {
+    let now = Date::now();
+    let entry = IdbCacheEntry {
+        store: store.name(),
+        key: key.clone(),
+        written_at: now,
+        accessed_at: now,
+        bytes: js_sys::JSON::stringify(value)
+            .map(|json| json.length())
+            .unwrap_or(0),
+    };
+    cache_store(store)?.put_with_key(
+        &serde_wasm_bindgen::to_value(&entry)?,
+        &entry_key(&store.name(), key),
+    )?;
+    Ok(())
+}
+
+pub(super) fn record_delete(store: &IdbObjectStore, key: &IdbKey) -> IdbResult<()> {
+    cache_store(store)?.delete(&entry_key(&store.name(), key))?;
+    Ok(())
+}
+
+pub(super) fn record_clear(store: &IdbObjectStore) -> IdbResult<()> {
+    cache_store(store)?.delete(&store_range(&store.name()).to_js()?)?;
+    Ok(())
+}
+
+// Refreshes the LRU timestamp within the read's transaction; the returned flag is
+// set when the entry has outlived its TTL and should be treated as missing. Only
+// single-key reads call this: bulk reads and cursors would turn paging into a write
+// per record, and TTL stores already retire expired records before they open.
+pub(super) fn record_access(
+    store: &IdbObjectStore,
+    key: &IdbKey,
+    policy: IdbCachePolicy,
+) -> IdbResult<Rc<Cell<bool>>> {
+    let expired = Rc::new(Cell::new(false));
+    let cache = cache_store(store)?;
+    let cache_key = entry_key(&store.name(), key);
+    let request = cache.get(&cache_key)?;
+    let success_request = request.clone();
+    let success_expired = expired.clone();
+    let on_success = Closure::once_into_js(move |_: web_sys::Event| {
+        let Some(mut entry) = success_request
+            .result()
+            .ok()
+            .and_then(|value| serde_wasm_bindgen::from_value::<IdbCacheEntry>(value).ok())
+        else {
+            return;
+        };
+        let now = Date::now();
+        if policy.is_expired(&entry, now) {
+            success_expired.set(true);
+            return;
+        }
+        entry.accessed_at = now;
+        if let Ok(value) = serde_wasm_bindgen::to_value(&entry) {
+            let _ = cache.put_with_key(&value, &cache_key);
+        }
+    });
+    request.set_onsuccess(Some(on_success.unchecked_ref()));
+    Ok(expired)
+}
+
+pub(super) async fn enforce<M: IdbStoreManager + ?Sized>() -> IdbResult<Vec<IdbKey>> {
+    let Some(policy) = M::cache_policy() else {
+        return Ok(Vec::new());
+    };
+    let store_name = M::store_name();
+    let transaction =
+        open_transaction::<M::Schema>(&[CACHE_STORE], IdbTransactionMode::Readonly).await?;
+    let cache = transaction
+        .object_store(CACHE_STORE)
+        .map_err(|_| IdbError::StoreMissing(CACHE_STORE.to_string()))?;
+    let entries = IdbQuery::new()
+        .range(store_range(store_name))
+        .collect(&cache)?
+        .await??
+        .into_iter()
+        .map(|entry| Ok(serde_wasm_bindgen::from_value::<IdbCacheEntry>(entry)?))
+        .collect::<IdbResult<Vec<_>>>()?;
+    let victims = policy.victims(&entries, Date::now());
+    if victims.is_empty() {
+        return Ok(victims);
+    }
+    let transaction =
+        open_transaction::<M::Schema>(&[store_name, CACHE_STORE], IdbTransactionMode::Readwrite)
+            .await?;
+    let store = transaction.object_store(store_name)?;
+    for key in &victims {
+        store.delete(&key.to_js())?;
+        record_delete(&store, key)?;
+    }
+    await_transaction(&transaction, Rc::new(RefCell::new(()))).await??;
+    notify_change(M::db_name(), store_name, IdbChange::Delete(victims.clone()));
+    Ok(victims)
+}
+
+// Writes in a burst share one pass per store, and pure entry-count policies only
+// scan once the store overshoots its limit by `ENFORCE_SLACK`.
+pub(super) fn spawn_enforce<M: IdbStoreManager + ?Sized>() {
+    let Some(policy) = M::cache_policy() else {
+        return;
+    };
+    let store = (M::db_name(), M::store_name());
+    if !SCHEDULED.with(|scheduled| scheduled.borrow_mut().insert(store)) {
+        return;
+    }
+    spawn_local(async move {
+        sleep(ENFORCE_DELAY).await;
+        SCHEDULED.with(|scheduled| scheduled.borrow_mut().remove(&store));
+        let enforced = async {
+            if !over_limit::<M>(policy).await? {
+                return Ok(Vec::new());
+            }
+            enforce::<M>().await
+        };
+        if let Err(error) = enforced.await {
+            warn!(
+                "Cache eviction failed for ",
+                M::store_name(),
+                error.to_string()
+            );
+        }
+    });
+}
+
+// TTLs and byte limits need the entries themselves, so only a bare entry limit can
+// be checked with a count.
+async fn over_limit<M: IdbStoreManager + ?Sized>(policy: IdbCachePolicy) -> IdbResult<bool> {
+    let Some(max_entries) = policy
+        .max_entries
+        .filter(|_| policy.ttl.is_none() && policy.max_bytes.is_none())
+    else {
+        return Ok(true);
+    };
+    let transaction =
+        open_transaction::<M::Schema>(&[CACHE_STORE], IdbTransactionMode::Readonly).await?;
+    let cache = transaction
+        .object_store(CACHE_STORE)
+        .map_err(|_| IdbError::StoreMissing(CACHE_STORE.to_string()))?;
+    let request = cache.count_with_key(&store_range(M::store_name()).to_js()?)?;
+    let count = request_result(&request).await??.as_f64().unwrap_or(0.0);
+    Ok(count > max_entries as f64 * (1.0 + ENFORCE_SLACK))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+pub struct IdbStorageEstimate {
+    #[serde(default)]
+    pub usage: f64,
+    #[serde(default)]
+    pub quota: f64,
+}
+impl IdbStorageEstimate {
+    pub async fn request() -> IdbResult<Self> {
+        let estimate = JsFuture::from(storage_manager()?.estimate()?).await?;
+        Ok(serde_wasm_bindgen::from_value(estimate)?)
+    }
+    pub fn usage_ratio(&self) -> f64 {
+        match self.quota > 0.0 {
+            true => self.usage / self.quota,
+            false => 0.0,
+        }
+    }
+    pub fn remaining(&self) -> f64 {
+        (self.quota - self.usage).max(0.0)
+    }
+}
+
+fn storage_manager() -> IdbResult<StorageManager> {
+    let window =
+        web_sys::window().ok_or(IdbError::OpenFailed("No window available.".to_string()))?;
+    Ok(window.navigator().storage())
+}
+
+pub async fn request_persistent_storage() -> IdbResult<bool> {
+    let persisted = JsFuture::from(storage_manager()?.persist()?).await?;
+    Ok(persisted.as_bool().unwrap_or(false))
+}
+
+pub async fn is_storage_persisted() -> IdbResult<bool> {
+    let persisted = JsFuture::from(storage_manager()?.persisted()?).await?;
+    Ok(persisted.as_bool().unwrap_or(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{IdbCacheEntry, IdbCachePolicy};
+    use crate::browser_api::indexed_db::query::IdbKey;
+
+    fn entry(key: &str, written_at: f64, accessed_at: f64, bytes: u32) -> IdbCacheEntry {
+        IdbCacheEntry {
+            store: "cache".to_string(),
+            key: IdbKey::from(key),
+            written_at,
+            accessed_at,
+            bytes,
+        }
+    }
+
+    #[test]
+    fn victims_evict_expired_then_least_recently_used() {
+        let entries = vec![
+            entry("fresh", 900.0, 950.0, 10),
+            entry("stale", 0.0, 990.0, 10),
+            entry("old", 800.0, 800.0, 10),
+            entry("recent", 900.0, 999.0, 10),
+        ];
+        let policy = IdbCachePolicy::new()
+            .ttl(Duration::from_millis(500))
+            .max_entries(2);
+        assert_eq!(
+            policy.victims(&entries, 1000.0),
+            vec![IdbKey::from("stale"), IdbKey::from("old")]
+        );
+        let policy = IdbCachePolicy::new().max_bytes(25);
+        assert_eq!(
+            policy.victims(&entries, 1000.0),
+            vec![IdbKey::from("old"), IdbKey::from("fresh")]
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use std::{cell::RefCell, collections::HashMap};
+
+use gloo::console::{error, warn};
+use gloo_timers::callback::Timeout;
+use js_sys::Array;
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use web_sys::{IdbDatabase, IdbTransaction, IdbTransactionMode, IdbVersionChangeEvent};
+use yew::platform::pinned::oneshot::{self, Receiver, Sender};
+
+use super::{schema::IdbSchema, IdbError, IdbResult};
+
+const BLOCKED_TIMEOUT_MS: u32 = 10_000;
+
+enum Connection {
+    Opening(Vec<Sender<IdbResult<IdbDatabase>>>),
+    Open(IdbDatabase),
+}
+
+thread_local! {
+    static CONNECTIONS: RefCell<HashMap<&'static str, Connection>> = RefCell::new(HashMap::new());
+}
+
+pub fn open_database<S: IdbSchema>() -> IdbResult<Receiver<IdbResult<IdbDatabase>>> {
+    let (sender, receiver) = oneshot::channel();
+    let start_open = CONNECTIONS.with(|connections| {
+        let mut connections = connections.borrow_mut();
+        match connections.get_mut(S::db_name()) {
+            Some(Connection::Open(database)) => {
+                let _ = sender.send(Ok(database.clone()));
+                false
+            }
+            Some(Connection::Opening(waiters)) => {
+                waiters.push(sender);
+                false
+            }
+            None => {
+                connections.insert(S::db_name(), Connection::Opening(vec![sender]));
+                true
+            }
+        }
+    });
+    if start_open {
+        if let Err(error) = request_open::<S>() {
+            resolve(S::db_name(), Err(error));
+        }
+    }
+    Ok(receiver)
+}
+
+pub fn close_database<S: IdbSchema>() {
+    if let Some(database) = evict(S::db_name(), None) {
+        database.close();
+    }
+}
+
+pub(super) async fn open_transaction<S: IdbSchema>(
+    store_names: &[&str],
+    mode: IdbTransactionMode,
+) -> IdbResult<IdbTransaction> {
+    let names: Array = store_names
+        .iter()
+        .map(|name| JsValue::from_str(name))
+        .collect();
+    let database = open_database::<S>()?.await??;
+    match database.transaction_with_str_sequence_and_mode(&names, mode) {
+        Ok(transaction) => Ok(transaction),
+        Err(error) if is_closed_error(&error) => {
+            // The cached connection was closed underneath us, open a fresh one.
+            evict(S::db_name(), Some(&database));
+            open_database::<S>()?
+                .await??
+                .transaction_with_str_sequence_and_mode(&names, mode)
+                .map_err(|_| IdbError::StoreMissing(store_names.join(", ")))
+        }
+        Err(_) => Err(IdbError::StoreMissing(store_names.join(", "))),
+    }
+}
+
+fn is_closed_error(error: &JsValue) -> bool {
+    error
+        .dyn_ref::<web_sys::DomException>()
+        .is_some_and(|exception| exception.name() == "InvalidStateError")
+}
+
+fn evict(db_name: &str, database: Option<&IdbDatabase>) -> Option<IdbDatabase> {
+    CONNECTIONS.with(|connections| {
+        let mut connections = connections.borrow_mut();
+        let cached = matches!(
+            connections.get(db_name),
+            Some(Connection::Open(open)) if database.is_none_or(|database| database == open)
+        );
+        match cached {
+            true => match connections.remove(db_name) {
+                Some(Connection::Open(database)) => Some(database),
+                _ => None,
+            },
+            false => None,
+        }
+    })
+}
+
+fn resolve(db_name: &'static str, result: IdbResult<IdbDatabase>) {
+    let waiters = CONNECTIONS.with(|connections| {
+        let mut connections = connections.borrow_mut();
+        let waiters = match connections.remove(db_name) {
+            Some(Connection::Opening(waiters)) => waiters,
+            _ => Vec::new(),
+        };
+        if let Ok(database) = &result {
+            connections.insert(db_name, Connection::Open(database.clone()));
+        }
+        waiters
+    });
+    for waiter in waiters {
+        let _ = waiter.send(result.clone());
+    }
+}
+
+// The stuck open is forgotten so the next caller starts a fresh one instead of
+// queueing behind it.
+fn fail_waiters(db_name: &'static str, error: IdbError) {
+    let waiters = CONNECTIONS.with(|connections| {
+        let mut connections = connections.borrow_mut();
+        match connections.get(db_name) {
+            Some(Connection::Opening(_)) => match connections.remove(db_name) {
+                Some(Connection::Opening(waiters)) => waiters,
+                _ => Vec::new(),
+            },
+            _ => Vec::new(),
+        }
+    });
+    for waiter in waiters {
+        let _ = waiter.send(Err(error.clone()));
+    }
+}
+
+fn watch_connection(db_name: &'static str, database: &IdbDatabase) {
+    let version_database = database.clone();
+    let on_version_change = Closure::once_into_js(move |_: IdbVersionChangeEvent| {
+        // Another tab is upgrading the schema, release our connection so it can proceed.
+        evict(db_name, Some(&version_database));
+        version_database.close();
+    });
+    let close_database = database.clone();
+    let on_close = Closure::once_into_js(move |_: web_sys::Event| {
+        evict(db_name, Some(&close_database));
+    });
+    database.set_onversionchange(Some(on_version_change.unchecked_ref()));
+    database.set_onclose(Some(on_close.unchecked_ref()));
+}
+
+fn request_open<S: IdbSchema>() -> IdbResult<()> {
+    let db_name = S::db_name();
+    let window =
+        web_sys::window().ok_or(IdbError::OpenFailed("No window available.".to_string()))?;
+    let idb_factory = window
+        .indexed_db()?
+        .ok_or(IdbError::OpenFailed("No IndexedDB".to_string()))?;
+    let idb_open_request = idb_factory.open_with_u32(db_name, S::db_version())?;
+    let success_request = idb_open_request.clone();
+    let on_success = Closure::once_into_js(move |_: web_sys::Event| {
+        let database = success_request
+            .result()
+            .and_then(|db| db.dyn_into::<IdbDatabase>())
+            .map_err(|e| IdbError::OpenFailed(format!("{:?}", e)));
+        if let Ok(database) = &database {
+            watch_connection(db_name, database);
+        }
+        resolve(db_name, database);
+    });
+    let error_request = idb_open_request.clone();
+    let on_error = Closure::once_into_js(move |_: web_sys::Event| {
+        let error = match IdbError::from_request(&error_request) {
+            IdbError::Transaction(msg) => IdbError::OpenFailed(msg),
+            error => error,
+        };
+        resolve(db_name, Err(error));
+    });
+    let on_blocked = Closure::once_into_js(move |_: web_sys::Event| {
+        warn!("IndexedDB upgrade blocked by another connection: ", db_name);
+        Timeout::new(BLOCKED_TIMEOUT_MS, move || {
+            fail_waiters(db_name, IdbError::Blocked);
+        })
+        .forget();
+    });
+    let upgrade_request = idb_open_request.clone();
+    let on_upgrade_needed = Closure::once_into_js(move |event: IdbVersionChangeEvent| {
+        if let Err(e) = S::upgrade(&upgrade_request, &event) {
+            error!(e.to_string());
+            if let Some(transaction) = upgrade_request.transaction() {
+                let _ = transaction.abort();
+            }
+        }
+    });
+    idb_open_request.set_onerror(Some(on_error.unchecked_ref()));
+    idb_open_request.set_onsuccess(Some(on_success.unchecked_ref()));
+    idb_open_request.set_onblocked(Some(on_blocked.unchecked_ref()));
+    idb_open_request.set_onupgradeneeded(Some(on_upgrade_needed.unchecked_ref()));
+    Ok(())
+}
@@ -0,0 +1,224 @@
+use std::{
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{
+    channel::mpsc::{unbounded, UnboundedReceiver},
+    Future, Stream,
+};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use web_sys::{IdbCursor, IdbCursorWithValue, IdbObjectStore, IdbRequest};
+use yew::platform::pinned::oneshot::Receiver;
+
+use super::{
+    query::{IdbKey, IdbQuery},
+    IdbError, IdbResult,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdbCursorMode {
+    #[default]
+    Values,
+    KeysOnly,
+}
+
+pub struct IdbCursorEntry<T> {
+    pub key: IdbKey,
+    pub primary_key: IdbKey,
+    pub value: Option<T>,
+    cursor: IdbCursor,
+}
+impl<T> IdbCursorEntry<T> {
+    pub fn update<V>(&self, value: V) -> IdbResult<()>
+    where
+        V: TryInto<JsValue, Error = JsValue>,
+    {
+        self.cursor.update(&value.try_into()?)?;
+        Ok(())
+    }
+    pub fn delete(&self) -> IdbResult<()> {
+        self.cursor.delete()?;
+        Ok(())
+    }
+}
+
+struct CursorHandle {
+    request: IdbRequest,
+    events: UnboundedReceiver<IdbResult<Option<IdbCursor>>>,
+    _on_success: Closure<dyn FnMut(web_sys::Event)>,
+    _on_error: Closure<dyn FnMut(web_sys::Event)>,
+}
+impl CursorHandle {
+    fn open(store: &IdbObjectStore, query: &IdbQuery, mode: IdbCursorMode) -> IdbResult<Self> {
+        let request = query.open_cursor(store, mode == IdbCursorMode::KeysOnly)?;
+        let (sender, events) = unbounded();
+        let success_sender = sender.clone();
+        let success_request = request.clone();
+        let on_success = Closure::<dyn FnMut(web_sys::Event)>::new(move |_: web_sys::Event| {
+            let cursor = success_request
+                .result()
+                .map(|cursor| cursor.dyn_into::<IdbCursor>().ok())
+                .map_err(IdbError::from);
+            let _ = success_sender.unbounded_send(cursor);
+        });
+        let error_request = request.clone();
+        let on_error = Closure::<dyn FnMut(web_sys::Event)>::new(move |_: web_sys::Event| {
+            let _ = sender.unbounded_send(Err(IdbError::from_request(&error_request)));
+        });
+        request.set_onsuccess(Some(on_success.as_ref().unchecked_ref()));
+        request.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+        Ok(Self {
+            request,
+            events,
+            _on_success: on_success,
+            _on_error: on_error,
+        })
+    }
+}
+impl Drop for CursorHandle {
+    fn drop(&mut self) {
+        self.request.set_onsuccess(None);
+        self.request.set_onerror(None);
+    }
+}
+
+enum CursorState {
+    Opening(Receiver<IdbResult<IdbObjectStore>>),
+    Iterating {
+        handle: CursorHandle,
+        current: Option<IdbCursor>,
+        skip: u32,
+        remaining: Option<u32>,
+    },
+    Done,
+}
+
+// The cursor only advances when polled. Awaiting unrelated work between items lets
+// IndexedDB auto-commit the transaction, after which the next poll yields an error.
+pub struct IdbCursorStream<T> {
+    query: IdbQuery,
+    mode: IdbCursorMode,
+    state: CursorState,
+    _marker: PhantomData<T>,
+}
+impl<T> IdbCursorStream<T> {
+    pub(super) fn new(
+        object_store_request: Receiver<IdbResult<IdbObjectStore>>,
+        query: IdbQuery,
+        mode: IdbCursorMode,
+    ) -> Self {
+        Self {
+            query,
+            mode,
+            state: CursorState::Opening(object_store_request),
+            _marker: PhantomData,
+        }
+    }
+    fn entry(&self, cursor: IdbCursor) -> IdbResult<IdbCursorEntry<T>>
+    where
+        T: TryFrom<JsValue, Error = JsValue>,
+    {
+        let key = IdbKey::from_js(&cursor.key()?)?;
+        let primary_key = IdbKey::from_js(&cursor.primary_key()?)?;
+        let value = match self.mode {
+            IdbCursorMode::KeysOnly => None,
+            IdbCursorMode::Values => {
+                let value = cursor.unchecked_ref::<IdbCursorWithValue>().value()?;
+                Some(T::try_from(value)?)
+            }
+        };
+        Ok(IdbCursorEntry {
+            key,
+            primary_key,
+            value,
+            cursor,
+        })
+    }
+}
+impl<T> Stream for IdbCursorStream<T>
+where
+    T: TryFrom<JsValue, Error = JsValue> + Unpin,
+{
+    type Item = IdbResult<IdbCursorEntry<T>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            match &mut this.state {
+                CursorState::Done => return Poll::Ready(None),
+                CursorState::Opening(object_store_request) => {
+                    let opened = match Pin::new(object_store_request).poll(cx) {
+                        Poll::Pending => return Poll::Pending,
+                        Poll::Ready(store) => store
+                            .map_err(IdbError::from)
+                            .and_then(|store| store)
+                            .and_then(|store| CursorHandle::open(&store, &this.query, this.mode)),
+                    };
+                    match opened {
+                        Ok(_) if this.query.limit == Some(0) => this.state = CursorState::Done,
+                        Ok(handle) => {
+                            this.state = CursorState::Iterating {
+                                handle,
+                                current: None,
+                                skip: this.query.offset,
+                                remaining: this.query.limit,
+                            }
+                        }
+                        Err(error) => {
+                            this.state = CursorState::Done;
+                            return Poll::Ready(Some(Err(error)));
+                        }
+                    }
+                }
+                CursorState::Iterating {
+                    handle,
+                    current,
+                    skip,
+                    remaining,
+                } => {
+                    if let Some(cursor) = current.take() {
+                        if *remaining == Some(0) {
+                            this.state = CursorState::Done;
+                            continue;
+                        }
+                        if let Err(error) = cursor.continue_() {
+                            this.state = CursorState::Done;
+                            return Poll::Ready(Some(Err(error.into())));
+                        }
+                    }
+                    let event = match Pin::new(&mut handle.events).poll_next(cx) {
+                        Poll::Pending => return Poll::Pending,
+                        Poll::Ready(event) => event,
+                    };
+                    let cursor = match event {
+                        Some(Ok(Some(cursor))) => cursor,
+                        Some(Err(error)) => {
+                            this.state = CursorState::Done;
+                            return Poll::Ready(Some(Err(error)));
+                        }
+                        Some(Ok(None)) | None => {
+                            this.state = CursorState::Done;
+                            continue;
+                        }
+                    };
+                    if *skip > 0 {
+                        let advance = cursor.advance(*skip);
+                        *skip = 0;
+                        if let Err(error) = advance {
+                            this.state = CursorState::Done;
+                            return Poll::Ready(Some(Err(error.into())));
+                        }
+                        continue;
+                    }
+                    if let Some(remaining) = remaining {
+                        *remaining -= 1;
+                    }
+                    *current = Some(cursor.clone());
+                    return Poll::Ready(Some(this.entry(cursor)));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::future::Future;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use nostro2::userkeys::UserKeys;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+use wasm_bindgen_futures::spawn_local;
+use web_sys::CryptoKey;
+use yew::platform::pinned::oneshot::{self, Receiver};
+
+use super::{
+    backend::{IdbBackend, IdbStore, IndexedDbBackend},
+    batch::IdbBatchReport,
+    query::{IdbKey, IdbQuery},
+    IdbError, IdbResult, IdbStoreManager,
+};
+use crate::browser_api::crypto::{aes_gcm_decrypt, aes_gcm_encrypt, derive_storage_key};
+
+const STORAGE_KEY_INFO: &str = "minions-idb-encryption";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IdbCipher {
+    key: CryptoKey,
+}
+impl IdbCipher {
+    pub fn new(key: CryptoKey) -> Self {
+        Self { key }
+    }
+    pub async fn from_user_keys(user_keys: &UserKeys) -> IdbResult<Self> {
+        Ok(Self::new(
+            derive_storage_key(user_keys, STORAGE_KEY_INFO).await?,
+        ))
+    }
+    async fn seal<M: IdbStoreManager>(&self, value: &Value) -> IdbResult<SealedValue> {
+        let plaintext = serde_json::to_vec(value)?;
+        let (iv, data) = aes_gcm_encrypt(&self.key, &plaintext, &associated_data::<M>()).await?;
+        Ok(SealedValue {
+            iv: STANDARD.encode(iv),
+            data: STANDARD.encode(data),
+        })
+    }
+    async fn open<M: IdbStoreManager>(&self, sealed: SealedValue) -> IdbResult<Value> {
+        let decode = |field: &str| {
+            STANDARD
+                .decode(field)
+                .map_err(|e| IdbError::Decryption(e.to_string()))
+        };
+        let plaintext = aes_gcm_decrypt(
+            &self.key,
+            &decode(&sealed.iv)?,
+            &decode(&sealed.data)?,
+            &associated_data::<M>(),
+        )
+        .await
+        .map_err(|e| IdbError::Decryption(format!("{:?}", e)))?;
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SealedValue {
+    iv: String,
+    data: String,
+}
+
+// Binds each ciphertext to its store so sealed values cannot be moved between stores.
+fn associated_data<M: IdbStoreManager>() -> Vec<u8> {
+    format!("{}/{}", M::db_name(), M::store_name()).into_bytes()
+}
+
+// Stores with an in-line key path still need the key readable on the stored value.
+fn with_key_path(mut envelope: Value, key_path: &str, key: &IdbKey) -> IdbResult<Value> {
+    let mut fields: Vec<&str> = key_path.split('.').collect();
+    let last = fields.pop().unwrap_or(key_path);
+    let mut target = &mut envelope;
+    for field in fields {
+        target = target
+            .as_object_mut()
+            .ok_or(IdbError::Serialization(format!(
+                "Invalid key path: {}",
+                key_path
+            )))?
+            .entry(field)
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+    target
+        .as_object_mut()
+        .ok_or(IdbError::Serialization(format!(
+            "Invalid key path: {}",
+            key_path
+        )))?
+        .insert(last.to_string(), serde_json::to_value(key)?);
+    Ok(envelope)
+}
+
+fn spawn_result<T, F>(future: F) -> IdbResult<Receiver<IdbResult<T>>>
+where
+    T: 'static,
+    F: Future<Output = IdbResult<T>> + 'static,
+{
+    let (sender, receiver) = oneshot::channel();
+    spawn_local(async move {
+        let _ = sender.send(future.await);
+    });
+    Ok(receiver)
+}
+
+// Only the primary key stays readable; index queries are rejected because the
+// indexed fields are sealed along with the rest of the record.
+#[derive(Clone, Debug)]
+pub struct EncryptedBackend<B = IndexedDbBackend> {
+    inner: B,
+    cipher: IdbCipher,
+}
+impl EncryptedBackend {
+    pub fn new(cipher: IdbCipher) -> Self {
+        Self::with_backend(IndexedDbBackend, cipher)
+    }
+}
+impl<B> EncryptedBackend<B> {
+    pub fn with_backend(inner: B, cipher: IdbCipher) -> Self {
+        Self { inner, cipher }
+    }
+}
+impl<B: IdbBackend + Clone + 'static> IdbBackend for EncryptedBackend<B> {
+    const ENCRYPTS: bool = true;
+    fn put<M: IdbStoreManager>(
+        &self,
+        key: IdbKey,
+        value: Value,
+    ) -> IdbResult<Receiver<IdbResult<()>>> {
+        let (inner, cipher) = (self.inner.clone(), self.cipher.clone());
+        spawn_result(async move {
+            let mut envelope = serde_json::to_value(cipher.seal::<M>(&value).await?)?;
+            if let Some(key_path) = M::store_schema().key_path {
+                envelope = with_key_path(envelope, key_path, &key)?;
+            }
+            inner.put::<M>(key, envelope)?.await?
+        })
+    }
+    fn put_many<M: IdbStoreManager>(
+        &self,
+        records: Vec<(IdbKey, Value)>,
+    ) -> IdbResult<Receiver<IdbResult<IdbBatchReport>>> {
+        let (inner, cipher) = (self.inner.clone(), self.cipher.clone());
+        spawn_result(async move {
+            let key_path = M::store_schema().key_path;
+            let mut sealed = Vec::new();
+            for (key, value) in records {
+                let mut envelope = serde_json::to_value(cipher.seal::<M>(&value).await?)?;
+                if let Some(key_path) = key_path {
+                    envelope = with_key_path(envelope, key_path, &key)?;
+                }
+                sealed.push((key, envelope));
+            }
+            inner.put_many::<M>(sealed)?.await?
+        })
+    }
+    fn get<M: IdbStoreManager>(
+        &self,
+        key: IdbKey,
+    ) -> IdbResult<Receiver<IdbResult<Option<Value>>>> {
+        let (inner, cipher) = (self.inner.clone(), self.cipher.clone());
+        spawn_result(async move {
+            match inner.get::<M>(key)?.await?? {
+                Some(envelope) => Ok(Some(
+                    cipher.open::<M>(serde_json::from_value(envelope)?).await?,
+                )),
+                None => Ok(None),
+            }
+        })
+    }
+    fn delete<M: IdbStoreManager>(&self, key: IdbKey) -> IdbResult<Receiver<IdbResult<()>>> {
+        self.inner.delete::<M>(key)
+    }
+    fn delete_many<M: IdbStoreManager>(
+        &self,
+        keys: Vec<IdbKey>,
+    ) -> IdbResult<Receiver<IdbResult<IdbBatchReport>>> {
+        self.inner.delete_many::<M>(keys)
+    }
+    fn clear<M: IdbStoreManager>(&self) -> IdbResult<Receiver<IdbResult<()>>> {
+        self.inner.clear::<M>()
+    }
+    fn query<M: IdbStoreManager>(
+        &self,
+        query: IdbQuery,
+    ) -> IdbResult<Receiver<IdbResult<Vec<Value>>>> {
+        if let Some(index) = query.index {
+            return Err(IdbError::Transaction(format!(
+                "Index {} is not available on encrypted store {}",
+                index,
+                M::store_name()
+            )));
+        }
+        let (inner, cipher) = (self.inner.clone(), self.cipher.clone());
+        spawn_result(async move {
+            let mut values = Vec::new();
+            for envelope in inner.query::<M>(query)?.await?? {
+                values.push(cipher.open::<M>(serde_json::from_value(envelope)?).await?);
+            }
+            Ok(values)
+        })
+    }
+}
+
+impl<M> IdbStore<M, EncryptedBackend>
+where
+    M: IdbStoreManager + Serialize + DeserializeOwned,
+{
+    pub fn encrypted(cipher: IdbCipher) -> Self {
+        Self::new(EncryptedBackend::new(cipher))
+    }
+}
@@ -0,0 +1,184 @@
+use std::rc::Rc;
+
+use wasm_bindgen::JsValue;
+use yew::{
+    platform::{pinned::oneshot::Receiver, spawn_local},
+    prelude::*,
+};
+
+use super::{query::IdbQuery, IdbError, IdbResult, IdbStoreManager};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdbState<T> {
+    pub data: T,
+    pub loading: bool,
+    pub error: Option<IdbError>,
+}
+
+pub enum IdbStateAction<T> {
+    Loading,
+    Loaded(T),
+    Failed(IdbError),
+}
+impl<T: Clone> Reducible for IdbState<T> {
+    type Action = IdbStateAction<T>;
+
+    fn reduce(self: Rc<Self>, action: Self::Action) -> Rc<Self> {
+        match action {
+            IdbStateAction::Loading => Rc::new(IdbState {
+                data: self.data.clone(),
+                loading: true,
+                error: None,
+            }),
+            IdbStateAction::Loaded(data) => Rc::new(IdbState {
+                data,
+                loading: false,
+                error: None,
+            }),
+            IdbStateAction::Failed(error) => Rc::new(IdbState {
+                data: self.data.clone(),
+                loading: false,
+                error: Some(error),
+            }),
+        }
+    }
+}
+
+#[derive(Default, PartialEq)]
+struct Revision(u32);
+impl Reducible for Revision {
+    type Action = ();
+
+    fn reduce(self: Rc<Self>, _action: Self::Action) -> Rc<Self> {
+        Rc::new(Revision(self.0.wrapping_add(1)))
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct UseIdbHandle<M, T> {
+    pub data: T,
+    pub loading: bool,
+    pub error: Option<IdbError>,
+    pub save: Callback<M>,
+    pub delete: Callback<M>,
+    pub reload: Callback<()>,
+}
+
+#[hook]
+fn use_idb_loader<M, T, D, F>(deps: D, load: F) -> UseIdbHandle<M, T>
+where
+    M: IdbStoreManager + TryInto<JsValue, Error = JsValue> + 'static,
+    T: Clone + Default + PartialEq + 'static,
+    D: Clone + PartialEq + 'static,
+    F: Fn(&D) -> IdbResult<Receiver<IdbResult<T>>> + 'static,
+{
+    let state = use_reducer(|| IdbState {
+        data: T::default(),
+        loading: true,
+        error: None,
+    });
+    let revision = use_reducer(Revision::default);
+    let latest_load = use_mut_ref(|| 0u32);
+
+    let revision_dispatcher = revision.dispatcher();
+    use_effect_with((), move |_| {
+        let observer = M::observe_store(Callback::from(move |_| revision_dispatcher.dispatch(())));
+        move || drop(observer)
+    });
+
+    let load_state = state.dispatcher();
+    // Every load gets its own ticket; a deps change keeps the revision, so the
+    // revision alone cannot tell a stale response from the current one.
+    use_effect_with((deps, revision.0), move |(deps, _)| {
+        let ticket = latest_load.borrow().wrapping_add(1);
+        *latest_load.borrow_mut() = ticket;
+        load_state.dispatch(IdbStateAction::Loading);
+        match load(deps) {
+            Ok(receiver) => spawn_local(async move {
+                let result = receiver.await.map_err(IdbError::from).and_then(|data| data);
+                if *latest_load.borrow() != ticket {
+                    return;
+                }
+                match result {
+                    Ok(data) => load_state.dispatch(IdbStateAction::Loaded(data)),
+                    Err(error) => load_state.dispatch(IdbStateAction::Failed(error)),
+                }
+            }),
+            Err(error) => load_state.dispatch(IdbStateAction::Failed(error)),
+        }
+        || {}
+    });
+
+    let save_state = state.dispatcher();
+    let save = Callback::from(move |record: M| {
+        let save_state = save_state.clone();
+        let result = M::save_to_store(record);
+        spawn_local(async move {
+            let saved = async { result?.await? }.await;
+            if let Err(error) = saved {
+                save_state.dispatch(IdbStateAction::Failed(error));
+            }
+        });
+    });
+    let delete_state = state.dispatcher();
+    let delete = Callback::from(move |record: M| {
+        let delete_state = delete_state.clone();
+        let result = record.delete_from_store();
+        spawn_local(async move {
+            let deleted = async { result?.await? }.await;
+            if let Err(error) = deleted {
+                delete_state.dispatch(IdbStateAction::Failed(error));
+            }
+        });
+    });
+    let reload_dispatcher = revision.dispatcher();
+    let reload = Callback::from(move |_| reload_dispatcher.dispatch(()));
+
+    UseIdbHandle {
+        data: state.data.clone(),
+        loading: state.loading,
+        error: state.error.clone(),
+        save,
+        delete,
+        reload,
+    }
+}
+
+#[hook]
+pub fn use_idb_value<M>(key: &str) -> UseIdbHandle<M, Option<M>>
+where
+    M: IdbStoreManager
+        + TryFrom<JsValue, Error = JsValue>
+        + TryInto<JsValue, Error = JsValue>
+        + Clone
+        + PartialEq
+        + 'static,
+{
+    use_idb_loader::<M, Option<M>, _, _>(key.to_string(), |key: &String| M::retrieve::<M>(key))
+}
+
+#[hook]
+pub fn use_idb_all<M>() -> UseIdbHandle<M, Vec<M>>
+where
+    M: IdbStoreManager
+        + TryFrom<JsValue, Error = JsValue>
+        + TryInto<JsValue, Error = JsValue>
+        + Clone
+        + PartialEq
+        + 'static,
+{
+    use_idb_query::<M>(IdbQuery::new())
+}
+
+#[hook]
+pub fn use_idb_query<M>(query: IdbQuery) -> UseIdbHandle<M, Vec<M>>
+where
+    M: IdbStoreManager
+        + TryFrom<JsValue, Error = JsValue>
+        + TryInto<JsValue, Error = JsValue>
+        + Clone
+        + PartialEq
+        + 'static,
+{
+    use_idb_loader::<M, Vec<M>, _, _>(query, |query: &IdbQuery| M::query::<M>(query.clone()))
+}
@@ -0,0 +1,488 @@
+use std::{cell::RefCell, cmp::Ordering, collections::HashMap, rc::Rc};
+
+use serde_json::Value;
+use yew::platform::pinned::oneshot::{self, Receiver};
+
+use super::{
+    backend::IdbBackend,
+    batch::IdbBatchReport,
+    observer::{notify_local, IdbChange},
+    query::{IdbDirection, IdbKey, IdbQuery},
+    schema::{IdbIndexSchema, IdbSchema, IdbStoreSchema},
+    IdbError, IdbResult, IdbStoreManager,
+};
+
+type Records = Vec<(IdbKey, Value)>;
+
+// Stores are only available once a migration of the record's schema creates them,
+// matching what the IndexedDB backend would see after an upgrade.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBackend {
+    stores: Rc<RefCell<HashMap<(&'static str, &'static str), Records>>>,
+}
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    fn with_store<M, T, F>(&self, operation: F) -> IdbResult<T>
+    where
+        M: IdbStoreManager,
+        F: FnOnce(&mut Records, &IdbStoreSchema) -> IdbResult<T>,
+    {
+        let schema = <M::Schema as IdbSchema>::store_schemas()
+            .remove(M::store_name())
+            .ok_or_else(|| IdbError::StoreMissing(M::store_name().to_string()))?;
+        let mut stores = self.stores.borrow_mut();
+        let records = stores.entry((M::db_name(), M::store_name())).or_default();
+        operation(records, &schema)
+    }
+}
+
+fn ready<T>(result: IdbResult<T>) -> IdbResult<Receiver<IdbResult<T>>> {
+    let (sender, receiver) = oneshot::channel();
+    let _ = sender.send(result);
+    Ok(receiver)
+}
+
+fn compare(a: &IdbKey, b: &IdbKey) -> Ordering {
+    a.partial_cmp(b).unwrap_or(Ordering::Equal)
+}
+
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .try_fold(value, |value, field| value.get(field))
+}
+
+fn index_keys(index: &IdbIndexSchema, value: &Value) -> Vec<IdbKey> {
+    let parts: Option<Vec<&Value>> = index
+        .key_path
+        .iter()
+        .map(|path| lookup(value, path))
+        .collect();
+    match parts.as_deref() {
+        Some([Value::Array(items)]) if index.multi_entry => {
+            let mut keys: Vec<IdbKey> = Vec::new();
+            for key in items.iter().filter_map(|item| IdbKey::from_json(item).ok()) {
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+            keys
+        }
+        Some([part]) => IdbKey::from_json(part).into_iter().collect(),
+        Some(parts) => parts
+            .iter()
+            .map(|part| IdbKey::from_json(part))
+            .collect::<IdbResult<Vec<_>>>()
+            .map(IdbKey::Array)
+            .into_iter()
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+fn store_record(
+    records: &mut Records,
+    schema: &IdbStoreSchema,
+    key: IdbKey,
+    value: Value,
+) -> IdbResult<()> {
+    if key.partial_cmp(&key).is_none() {
+        return Err(IdbError::Serialization("Invalid key: NaN".to_string()));
+    }
+    for index in schema.indexes.iter().filter(|index| index.unique) {
+        let keys = index_keys(index, &value);
+        let taken = records.iter().any(|(other, record)| {
+            other != &key
+                && index_keys(index, record)
+                    .iter()
+                    .any(|existing| keys.contains(existing))
+        });
+        if taken {
+            return Err(IdbError::Constraint(format!(
+                "{}.{}",
+                schema.name, index.name
+            )));
+        }
+    }
+    match records.binary_search_by(|(existing, _)| compare(existing, &key)) {
+        Ok(position) => records[position].1 = value,
+        Err(position) => records.insert(position, (key, value)),
+    }
+    Ok(())
+}
+
+impl IdbBackend for MemoryBackend {
+    fn put<M: IdbStoreManager>(
+        &self,
+        key: IdbKey,
+        value: Value,
+    ) -> IdbResult<Receiver<IdbResult<()>>> {
+        let stored = self.with_store::<M, _, _>(|records, schema| {
+            store_record(records, schema, key.clone(), value)
+        });
+        if stored.is_ok() {
+            notify_local(M::db_name(), M::store_name(), IdbChange::Put(vec![key]));
+        }
+        ready(stored)
+    }
+    fn put_many<M: IdbStoreManager>(
+        &self,
+        records: Vec<(IdbKey, Value)>,
+    ) -> IdbResult<Receiver<IdbResult<IdbBatchReport>>> {
+        let mut saved = Vec::new();
+        let report = self.with_store::<M, _, _>(|stored, schema| {
+            let mut report = IdbBatchReport::default();
+            for (index, (key, value)) in records.into_iter().enumerate() {
+                match store_record(stored, schema, key.clone(), value) {
+                    Ok(()) => {
+                        report.succeeded += 1;
+                        saved.push(key);
+                    }
+                    Err(error) => report.failed.push((index, error)),
+                }
+            }
+            Ok(report)
+        });
+        if report.is_ok() {
+            notify_local(M::db_name(), M::store_name(), IdbChange::Put(saved));
+        }
+        ready(report)
+    }
+    fn get<M: IdbStoreManager>(
+        &self,
+        key: IdbKey,
+    ) -> IdbResult<Receiver<IdbResult<Option<Value>>>> {
+        ready(self.with_store::<M, _, _>(|records, _| {
+            Ok(records
+                .binary_search_by(|(existing, _)| compare(existing, &key))
+                .ok()
+                .map(|position| records[position].1.clone()))
+        }))
+    }
+    fn delete<M: IdbStoreManager>(&self, key: IdbKey) -> IdbResult<Receiver<IdbResult<()>>> {
+        let deleted = self.with_store::<M, _, _>(|records, _| {
+            if let Ok(position) = records.binary_search_by(|(existing, _)| compare(existing, &key))
+            {
+                records.remove(position);
+            }
+            Ok(())
+        });
+        if deleted.is_ok() {
+            notify_local(M::db_name(), M::store_name(), IdbChange::Delete(vec![key]));
+        }
+        ready(deleted)
+    }
+    fn delete_many<M: IdbStoreManager>(
+        &self,
+        keys: Vec<IdbKey>,
+    ) -> IdbResult<Receiver<IdbResult<IdbBatchReport>>> {
+        let deleted = self.with_store::<M, _, _>(|records, _| {
+            records.retain(|(existing, _)| !keys.contains(existing));
+            Ok(IdbBatchReport {
+                succeeded: keys.len(),
+                failed: Vec::new(),
+            })
+        });
+        if deleted.is_ok() {
+            notify_local(M::db_name(), M::store_name(), IdbChange::Delete(keys));
+        }
+        ready(deleted)
+    }
+    fn clear<M: IdbStoreManager>(&self) -> IdbResult<Receiver<IdbResult<()>>> {
+        let cleared = self.with_store::<M, _, _>(|records, _| {
+            records.clear();
+            Ok(())
+        });
+        if cleared.is_ok() {
+            notify_local(M::db_name(), M::store_name(), IdbChange::Clear);
+        }
+        ready(cleared)
+    }
+    fn query<M: IdbStoreManager>(
+        &self,
+        query: IdbQuery,
+    ) -> IdbResult<Receiver<IdbResult<Vec<Value>>>> {
+        ready(self.with_store::<M, _, _>(|records, schema| {
+            let mut entries: Vec<(IdbKey, &IdbKey, &Value)> = match query.index {
+                None => records
+                    .iter()
+                    .map(|(key, value)| (key.clone(), key, value))
+                    .collect(),
+                Some(name) => {
+                    let index = schema
+                        .indexes
+                        .iter()
+                        .find(|index| index.name == name)
+                        .ok_or_else(|| {
+                            IdbError::StoreMissing(format!("{}.{}", schema.name, name))
+                        })?;
+                    let mut entries: Vec<_> = records
+                        .iter()
+                        .flat_map(|(key, value)| {
+                            index_keys(index, value)
+                                .into_iter()
+                                .map(move |index_key| (index_key, key, value))
+                        })
+                        .collect();
+                    entries.sort_by(|a, b| compare(&a.0, &b.0).then_with(|| compare(a.1, b.1)));
+                    entries
+                }
+            };
+            entries.retain(|(index_key, _, _)| query.range.contains(index_key));
+            if matches!(
+                query.direction,
+                IdbDirection::NextUnique | IdbDirection::PrevUnique
+            ) {
+                entries.dedup_by(|next, previous| next.0 == previous.0);
+            }
+            if matches!(
+                query.direction,
+                IdbDirection::Prev | IdbDirection::PrevUnique
+            ) {
+                entries.reverse();
+            }
+            Ok(entries
+                .into_iter()
+                .skip(query.offset as usize)
+                .take(query.limit.map_or(usize::MAX, |limit| limit as usize))
+                .map(|(_, _, value)| value.clone())
+                .collect())
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use futures::executor::block_on;
+    use serde::{Deserialize, Serialize};
+    use yew::Callback;
+
+    use super::MemoryBackend;
+    use crate::browser_api::indexed_db::{
+        backend::IdbStore,
+        observer::{IdbChange, IdbChangeEvent},
+        query::{IdbKey, IdbQuery, IdbRange},
+        schema::{IdbIndexSchema, IdbMigration, IdbSchema, IdbStoreSchema},
+        IdbError, IdbStoreManager,
+    };
+    use crate::relay_pool::nostr_relay::UserRelay;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Note {
+        id: String,
+        kind: u32,
+        created_at: u64,
+        tags: Vec<String>,
+    }
+    impl Note {
+        fn new(id: &str, kind: u32, created_at: u64, tags: &[&str]) -> Self {
+            Self {
+                id: id.to_string(),
+                kind,
+                created_at,
+                tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Unregistered {
+        id: String,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Secret {
+        id: String,
+    }
+
+    struct TestDb;
+    impl IdbSchema for TestDb {
+        fn db_name() -> &'static str {
+            "test"
+        }
+        fn migrations() -> Vec<IdbMigration> {
+            vec![IdbMigration::new(1)
+                .create_store(Note::store_schema())
+                .create_store(Secret::store_schema())]
+        }
+    }
+    impl IdbStoreManager for Note {
+        type Schema = TestDb;
+        fn store_name() -> &'static str {
+            "notes"
+        }
+        fn document_key(&self) -> IdbKey {
+            IdbKey::from(self.id.as_str())
+        }
+        fn store_schema() -> IdbStoreSchema {
+            IdbStoreSchema::new(Self::store_name())
+                .key_path("id")
+                .index(IdbIndexSchema::compound(
+                    "kind_created",
+                    &["kind", "created_at"],
+                ))
+                .index(IdbIndexSchema::new("tags", "tags").multi_entry())
+                .index(IdbIndexSchema::new("created_at", "created_at").unique())
+        }
+    }
+    impl IdbStoreManager for Unregistered {
+        type Schema = TestDb;
+        fn store_name() -> &'static str {
+            "unregistered"
+        }
+        fn document_key(&self) -> IdbKey {
+            IdbKey::from(self.id.as_str())
+        }
+    }
+
+    impl IdbStoreManager for Secret {
+        type Schema = TestDb;
+        fn store_name() -> &'static str {
+            "secrets"
+        }
+        fn document_key(&self) -> IdbKey {
+            IdbKey::from(self.id.as_str())
+        }
+        fn encrypted() -> bool {
+            true
+        }
+    }
+
+    fn notes() -> IdbStore<Note, MemoryBackend> {
+        let store = IdbStore::new(MemoryBackend::new());
+        for note in [
+            Note::new("a", 1, 10, &["nostr", "rust"]),
+            Note::new("b", 1, 20, &["rust"]),
+            Note::new("c", 7, 30, &["nostr"]),
+            Note::new("d", 1, 40, &[]),
+        ] {
+            block_on(store.save(&note)).unwrap();
+        }
+        store
+    }
+
+    #[test]
+    fn user_relays_round_trip() {
+        let store: IdbStore<UserRelay, MemoryBackend> = IdbStore::new(MemoryBackend::new());
+        let relay = |url: &str| UserRelay {
+            url: url.to_string(),
+            read: true,
+            write: false,
+        };
+        block_on(async {
+            store.save(&relay("wss://b.relay")).await.unwrap();
+            store.save(&relay("wss://a.relay")).await.unwrap();
+            assert_eq!(
+                store.get("wss://b.relay").await.unwrap(),
+                Some(relay("wss://b.relay"))
+            );
+            let urls: Vec<String> = store
+                .all()
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|relay| relay.url)
+                .collect();
+            assert_eq!(urls, vec!["wss://a.relay", "wss://b.relay"]);
+            store.delete(&relay("wss://a.relay")).await.unwrap();
+            assert_eq!(store.get("wss://a.relay").await.unwrap(), None);
+            store.clear().await.unwrap();
+            assert!(store.all().await.unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn queries_use_indexes() {
+        let store = notes();
+        let ids = |notes: Vec<Note>| notes.into_iter().map(|note| note.id).collect::<Vec<_>>();
+        block_on(async {
+            let latest_kind_one = store
+                .query(
+                    IdbQuery::new()
+                        .index("kind_created")
+                        .range(IdbRange::prefix(vec![IdbKey::from(1u32)]))
+                        .reverse()
+                        .limit(2),
+                )
+                .await
+                .unwrap();
+            assert_eq!(ids(latest_kind_one), vec!["d", "b"]);
+            let tagged = store
+                .query(IdbQuery::new().index("tags").range(IdbRange::only("rust")))
+                .await
+                .unwrap();
+            assert_eq!(ids(tagged), vec!["a", "b"]);
+            let after = store
+                .query(IdbQuery::new().range(IdbRange::lower("b")).offset(1))
+                .await
+                .unwrap();
+            assert_eq!(ids(after), vec!["c", "d"]);
+        });
+    }
+
+    #[test]
+    fn unique_index_rejects_duplicates() {
+        let store = notes();
+        let duplicate = Note::new("e", 1, 20, &[]);
+        assert!(matches!(
+            block_on(store.save(&duplicate)),
+            Err(IdbError::Constraint(_))
+        ));
+        let replaced = Note::new("b", 3, 20, &[]);
+        block_on(store.save(&replaced)).unwrap();
+        assert_eq!(block_on(store.get("b")).unwrap(), Some(replaced));
+    }
+
+    #[test]
+    fn unregistered_store_is_missing() {
+        let store: IdbStore<Unregistered, MemoryBackend> = IdbStore::new(MemoryBackend::new());
+        let record = Unregistered {
+            id: "x".to_string(),
+        };
+        assert!(matches!(
+            block_on(store.save(&record)),
+            Err(IdbError::StoreMissing(_))
+        ));
+    }
+
+    #[test]
+    fn encrypted_stores_refuse_plaintext_records() {
+        let store: IdbStore<Secret, MemoryBackend> = IdbStore::new(MemoryBackend::new());
+        let secret = Secret {
+            id: "x".to_string(),
+        };
+        block_on(async {
+            assert!(matches!(
+                store.save(&secret).await,
+                Err(IdbError::Transaction(_))
+            ));
+            assert!(matches!(
+                store.get("x").await,
+                Err(IdbError::Transaction(_))
+            ));
+            assert!(matches!(store.all().await, Err(IdbError::Transaction(_))));
+            store.delete(&secret).await.unwrap();
+        });
+    }
+
+    #[test]
+    fn observers_see_local_changes() {
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let seen = changes.clone();
+        let _observer = Note::observe_store(Callback::from(move |event: IdbChangeEvent| {
+            seen.borrow_mut().push(event.change);
+        }));
+        let store = IdbStore::new(MemoryBackend::new());
+        block_on(async {
+            store.save(&Note::new("a", 1, 10, &[])).await.unwrap();
+            store.clear().await.unwrap();
+        });
+        assert_eq!(
+            *changes.borrow(),
+            vec![IdbChange::Put(vec![IdbKey::from("a")]), IdbChange::Clear]
+        );
+    }
+}
@@ -0,0 +1,539 @@
+pub mod backend;
+pub mod backup;
+pub mod batch;
+pub mod cache;
+pub mod connection;
+pub mod cursor;
+pub mod encrypted;
+pub mod hooks;
+pub mod memory;
+pub mod observer;
+pub mod query;
+pub mod schema;
+pub mod transaction;
+
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use serde::de::DeserializeOwned;
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{
+    DomException, IdbDatabase, IdbObjectStore, IdbRequest, IdbTransaction, IdbTransactionMode,
+};
+use yew::platform::pinned::oneshot::{self, Receiver, RecvError, Sender};
+use yew::Callback;
+
+use self::batch::{track_batch, IdbBatchReport};
+use self::cache::{
+    record_access, record_clear, record_delete, record_write, spawn_enforce, IdbCachePolicy,
+    CACHE_STORE,
+};
+use self::connection::{open_database, open_transaction};
+use self::cursor::{IdbCursorMode, IdbCursorStream};
+use self::observer::{notify_change, IdbChange, IdbChangeEvent, IdbObserver};
+use self::query::{IdbKey, IdbQuery};
+use self::schema::{IdbSchema, IdbStoreSchema};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdbError {
+    OpenFailed(String),
+    Blocked,
+    VersionError(String),
+    StoreMissing(String),
+    Constraint(String),
+    QuotaExceeded,
+    Serialization(String),
+    NotFound,
+    Decryption(String),
+    Transaction(String),
+}
+impl IdbError {
+    pub fn from_dom_exception(exception: &DomException) -> Self {
+        let message = exception.message();
+        match exception.name().as_str() {
+            "VersionError" => Self::VersionError(message),
+            "NotFoundError" => Self::StoreMissing(message),
+            "ConstraintError" => Self::Constraint(message),
+            "QuotaExceededError" => Self::QuotaExceeded,
+            "DataError" | "DataCloneError" => Self::Serialization(message),
+            _ => Self::Transaction(message),
+        }
+    }
+    fn from_request(request: &IdbRequest) -> Self {
+        match request.error() {
+            Ok(Some(exception)) => Self::from_dom_exception(&exception),
+            _ => Self::Transaction("Request failed without an error".to_string()),
+        }
+    }
+}
+impl fmt::Display for IdbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OpenFailed(msg) => write!(f, "Could not open database: {}", msg),
+            Self::Blocked => write!(f, "Database open blocked by another connection"),
+            Self::VersionError(msg) => write!(f, "Database version error: {}", msg),
+            Self::StoreMissing(msg) => write!(f, "Object store missing: {}", msg),
+            Self::Constraint(msg) => write!(f, "Constraint error: {}", msg),
+            Self::QuotaExceeded => write!(f, "Storage quota exceeded"),
+            Self::Serialization(msg) => write!(f, "Serialization error: {}", msg),
+            Self::NotFound => write!(f, "Record not found"),
+            Self::Decryption(msg) => write!(f, "Could not decrypt record: {}", msg),
+            Self::Transaction(msg) => write!(f, "Transaction error: {}", msg),
+        }
+    }
+}
+impl std::error::Error for IdbError {}
+impl From<JsValue> for IdbError {
+    fn from(value: JsValue) -> Self {
+        match value.dyn_ref::<DomException>() {
+            Some(exception) => Self::from_dom_exception(exception),
+            None => Self::Transaction(format!("{:?}", value)),
+        }
+    }
+}
+impl From<RecvError> for IdbError {
+    fn from(value: RecvError) -> Self {
+        Self::Transaction(value.to_string())
+    }
+}
+impl From<serde_json::Error> for IdbError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Serialization(value.to_string())
+    }
+}
+impl From<serde_wasm_bindgen::Error> for IdbError {
+    fn from(value: serde_wasm_bindgen::Error) -> Self {
+        Self::Serialization(value.to_string())
+    }
+}
+impl From<IdbError> for JsValue {
+    fn from(value: IdbError) -> Self {
+        JsValue::from_str(&value.to_string())
+    }
+}
+
+pub type IdbResult<T> = Result<T, IdbError>;
+
+struct SharedSender<T>(Rc<RefCell<Option<Sender<T>>>>);
+impl<T> Clone for SharedSender<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+impl<T> SharedSender<T> {
+    fn new(sender: Sender<T>) -> Self {
+        Self(Rc::new(RefCell::new(Some(sender))))
+    }
+    fn send(&self, value: T) {
+        if let Some(sender) = self.0.borrow_mut().take() {
+            let _ = sender.send(value);
+        }
+    }
+}
+
+fn request_result(request: &IdbRequest) -> Receiver<IdbResult<JsValue>> {
+    let result = Rc::new(RefCell::new(JsValue::UNDEFINED));
+    let success_result = result.clone();
+    let success_request = request.clone();
+    let on_success = Closure::once_into_js(move |_: web_sys::Event| {
+        if let Ok(value) = success_request.result() {
+            *success_result.borrow_mut() = value;
+        }
+    });
+    request.set_onsuccess(Some(on_success.unchecked_ref()));
+    match request.transaction() {
+        Some(transaction) => await_transaction(&transaction, result),
+        None => {
+            let (sender, receiver) = oneshot::channel();
+            let _ = sender.send(Err(IdbError::Transaction(
+                "Request has no transaction".to_string(),
+            )));
+            receiver
+        }
+    }
+}
+
+fn await_transaction<T: Default + 'static>(
+    transaction: &IdbTransaction,
+    result: Rc<RefCell<T>>,
+) -> Receiver<IdbResult<T>> {
+    let (sender, receiver) = oneshot::channel();
+    let sender = SharedSender::new(sender);
+    let complete_sender = sender.clone();
+    let on_complete = Closure::once_into_js(move |_: web_sys::Event| {
+        complete_sender.send(Ok(result.take()));
+    });
+    let error_sender = sender.clone();
+    let on_error = Closure::once_into_js(move |event: web_sys::Event| {
+        let error = match event
+            .target()
+            .and_then(|target| target.dyn_into::<IdbRequest>().ok())
+        {
+            Some(request) => IdbError::from_request(&request),
+            None => IdbError::Transaction("Unknown transaction error".to_string()),
+        };
+        error_sender.send(Err(error));
+    });
+    let abort_transaction = transaction.clone();
+    let on_abort = Closure::once_into_js(move |_: web_sys::Event| {
+        let error = match abort_transaction.error() {
+            Some(exception) => IdbError::from_dom_exception(&exception),
+            None => IdbError::Transaction("Transaction aborted".to_string()),
+        };
+        sender.send(Err(error));
+    });
+    transaction.set_oncomplete(Some(on_complete.unchecked_ref()));
+    transaction.set_onerror(Some(on_error.unchecked_ref()));
+    transaction.set_onabort(Some(on_abort.unchecked_ref()));
+    receiver
+}
+
+fn map_receiver<T, U, F>(receiver: Receiver<IdbResult<T>>, map: F) -> Receiver<IdbResult<U>>
+where
+    T: 'static,
+    U: 'static,
+    F: FnOnce(T) -> IdbResult<U> + 'static,
+{
+    let (sender, mapped) = oneshot::channel();
+    spawn_local(async move {
+        let result = receiver
+            .await
+            .map_err(IdbError::from)
+            .and_then(|result| result.and_then(map));
+        let _ = sender.send(result);
+    });
+    mapped
+}
+
+fn spawn_with_store<T, F>(
+    object_store_request: Receiver<IdbResult<IdbObjectStore>>,
+    operation: F,
+) -> Receiver<IdbResult<T>>
+where
+    T: 'static,
+    F: FnOnce(IdbObjectStore) -> IdbResult<Receiver<IdbResult<T>>> + 'static,
+{
+    let (sender, receiver) = oneshot::channel();
+    spawn_local(async move {
+        let result = async {
+            let object_store = object_store_request.await??;
+            operation(object_store)?.await?
+        }
+        .await;
+        let _ = sender.send(result);
+    });
+    receiver
+}
+
+pub trait IdbStoreManager: 'static {
+    type Schema: IdbSchema;
+    fn store_name() -> &'static str;
+    fn document_key(&self) -> IdbKey;
+    fn store_schema() -> IdbStoreSchema {
+        IdbStoreSchema::new(Self::store_name())
+    }
+    fn db_name() -> &'static str {
+        Self::Schema::db_name()
+    }
+    fn db_version() -> u32 {
+        Self::Schema::db_version()
+    }
+    fn cache_policy() -> Option<IdbCachePolicy> {
+        None
+    }
+    // Encrypted stores only take sealed records, written through `IdbStore::encrypted`;
+    // the plaintext methods below refuse them.
+    fn encrypted() -> bool {
+        false
+    }
+    fn enforce_cache_policy() -> IdbResult<Receiver<IdbResult<Vec<IdbKey>>>> {
+        let (sender, receiver) = oneshot::channel();
+        spawn_local(async move {
+            let _ = sender.send(cache::enforce::<Self>().await);
+        });
+        Ok(receiver)
+    }
+    fn observe_store(callback: Callback<IdbChangeEvent>) -> IdbObserver {
+        IdbObserver::new(Self::db_name(), Self::store_name(), callback)
+    }
+    // These write structured-clone values such as a `CryptoKey` straight to IndexedDB;
+    // serde records go through `IdbStore`, which also runs on the in-memory backend.
+    fn save_to_store(self) -> IdbResult<Receiver<IdbResult<()>>>
+    where
+        Self: TryInto<JsValue, Error = JsValue> + Sized,
+    {
+        require_plaintext::<Self>()?;
+        let object_store_request = Self::request_store_open()?;
+        let key = self.document_key();
+        let js_value: JsValue = self.try_into().map_err(IdbError::from)?;
+        let inline_key = Self::store_schema().key_path.is_some();
+        let cached = Self::cache_policy().is_some();
+        let (db_name, store_name) = (Self::db_name(), Self::store_name());
+        Ok(spawn_with_store(object_store_request, move |store| {
+            let request = put_record(&store, inline_key, &js_value, &key.to_js())?;
+            if cached {
+                record_write(&store, &key, &js_value)?;
+            }
+            Ok(map_receiver(request_result(&request), move |_| {
+                notify_change(db_name, store_name, IdbChange::Put(vec![key]));
+                spawn_enforce::<Self>();
+                Ok(())
+            }))
+        }))
+    }
+    fn save_many<I>(records: I) -> IdbResult<Receiver<IdbResult<IdbBatchReport>>>
+    where
+        I: IntoIterator<Item = Self>,
+        Self: TryInto<JsValue, Error = JsValue> + Sized,
+    {
+        require_plaintext::<Self>()?;
+        let object_store_request = Self::request_store_open()?;
+        let (keys, values): (Vec<IdbKey>, Vec<IdbResult<JsValue>>) = records
+            .into_iter()
+            .map(|record| {
+                let key = record.document_key();
+                (key, record.try_into().map_err(IdbError::from))
+            })
+            .unzip();
+        let inline_key = Self::store_schema().key_path.is_some();
+        let cached = Self::cache_policy().is_some();
+        let (db_name, store_name) = (Self::db_name(), Self::store_name());
+        Ok(spawn_with_store(object_store_request, move |store| {
+            let requests = values
+                .into_iter()
+                .zip(keys.iter())
+                .map(|(value, key)| {
+                    let value = value?;
+                    let request = put_record(&store, inline_key, &value, &key.to_js())?;
+                    if cached {
+                        record_write(&store, key, &value)?;
+                    }
+                    Ok(request)
+                })
+                .collect();
+            let report = track_batch(&store.transaction(), requests);
+            Ok(map_receiver(report, move |report| {
+                let saved = report.succeeded_keys(keys);
+                notify_change(db_name, store_name, IdbChange::Put(saved));
+                spawn_enforce::<Self>();
+                Ok(report)
+            }))
+        }))
+    }
+    // Stores with a key path read the key from the value; `key` still names the
+    // record for cache bookkeeping and change notifications.
+    fn save_value_to_store(value: JsValue, key: &str) -> IdbResult<Receiver<IdbResult<()>>> {
+        require_plaintext::<Self>()?;
+        let object_store_request = Self::request_store_open()?;
+        let key = IdbKey::from(key);
+        let inline_key = Self::store_schema().key_path.is_some();
+        let cached = Self::cache_policy().is_some();
+        let (db_name, store_name) = (Self::db_name(), Self::store_name());
+        Ok(spawn_with_store(object_store_request, move |store| {
+            let request = put_record(&store, inline_key, &value, &key.to_js())?;
+            if cached {
+                record_write(&store, &key, &value)?;
+            }
+            Ok(map_receiver(request_result(&request), move |_| {
+                notify_change(db_name, store_name, IdbChange::Put(vec![key]));
+                spawn_enforce::<Self>();
+                Ok(())
+            }))
+        }))
+    }
+    fn delete_from_store(&self) -> IdbResult<Receiver<IdbResult<()>>> {
+        let object_store_request = Self::request_store_open()?;
+        let key = self.document_key();
+        let cached = Self::cache_policy().is_some();
+        let (db_name, store_name) = (Self::db_name(), Self::store_name());
+        Ok(spawn_with_store(object_store_request, move |store| {
+            let request = store.delete(&key.to_js())?;
+            if cached {
+                record_delete(&store, &key)?;
+            }
+            Ok(map_receiver(request_result(&request), move |_| {
+                notify_change(db_name, store_name, IdbChange::Delete(vec![key]));
+                Ok(())
+            }))
+        }))
+    }
+    fn delete_many(records: &[Self]) -> IdbResult<Receiver<IdbResult<IdbBatchReport>>>
+    where
+        Self: Sized,
+    {
+        let object_store_request = Self::request_store_open()?;
+        let keys: Vec<IdbKey> = records.iter().map(|record| record.document_key()).collect();
+        let cached = Self::cache_policy().is_some();
+        let (db_name, store_name) = (Self::db_name(), Self::store_name());
+        Ok(spawn_with_store(object_store_request, move |store| {
+            let requests = keys
+                .iter()
+                .map(|key| {
+                    let request = store.delete(&key.to_js())?;
+                    if cached {
+                        record_delete(&store, key)?;
+                    }
+                    Ok(request)
+                })
+                .collect();
+            let report = track_batch(&store.transaction(), requests);
+            Ok(map_receiver(report, move |report| {
+                let deleted = report.succeeded_keys(keys);
+                notify_change(db_name, store_name, IdbChange::Delete(deleted));
+                Ok(report)
+            }))
+        }))
+    }
+    fn clear_store() -> IdbResult<Receiver<IdbResult<()>>> {
+        let object_store_request = Self::request_store_open()?;
+        let cached = Self::cache_policy().is_some();
+        let (db_name, store_name) = (Self::db_name(), Self::store_name());
+        Ok(spawn_with_store(object_store_request, move |store| {
+            let request = store.clear()?;
+            if cached {
+                record_clear(&store)?;
+            }
+            Ok(map_receiver(request_result(&request), move |_| {
+                notify_change(db_name, store_name, IdbChange::Clear);
+                Ok(())
+            }))
+        }))
+    }
+    fn retrieve<T>(key: &str) -> IdbResult<Receiver<IdbResult<Option<T>>>>
+    where
+        T: TryFrom<JsValue> + 'static,
+        T::Error: fmt::Debug,
+    {
+        require_plaintext::<Self>()?;
+        let object_store_request = Self::request_store_open()?;
+        let key = IdbKey::from(key);
+        let policy = Self::cache_policy();
+        Ok(spawn_with_store(object_store_request, move |store| {
+            let request = store.get(&key.to_js())?;
+            let expired = match policy {
+                Some(policy) => Some(record_access(&store, &key, policy)?),
+                None => None,
+            };
+            Ok(map_receiver(request_result(&request), move |result| {
+                let expired = expired.is_some_and(|expired| expired.get());
+                if result.is_null() || result.is_undefined() || expired {
+                    return Ok(None);
+                }
+                T::try_from(result)
+                    .map(Some)
+                    .map_err(|e| IdbError::Serialization(format!("{:?}", e)))
+            }))
+        }))
+    }
+    fn retrieve_all_from_store<T>() -> IdbResult<Receiver<IdbResult<Vec<T>>>>
+    where
+        T: TryFrom<JsValue, Error = JsValue> + 'static + DeserializeOwned,
+    {
+        require_plaintext::<Self>()?;
+        let object_store_request = Self::request_read_open()?;
+        Ok(spawn_with_store(object_store_request, move |store| {
+            let request = store.get_all()?;
+            Ok(map_receiver(request_result(&request), |result| {
+                let js_array: js_sys::Array = result
+                    .dyn_into()
+                    .map_err(|_| IdbError::Serialization("Expected an array".to_string()))?;
+                js_array
+                    .iter()
+                    .map(|value| T::try_from(value).map_err(IdbError::from))
+                    .collect()
+            }))
+        }))
+    }
+    fn query<T>(query: IdbQuery) -> IdbResult<Receiver<IdbResult<Vec<T>>>>
+    where
+        T: TryFrom<JsValue, Error = JsValue> + 'static,
+    {
+        require_plaintext::<Self>()?;
+        let object_store_request = Self::request_read_open()?;
+        Ok(spawn_with_store(object_store_request, move |store| {
+            let values = query.collect(&store)?;
+            Ok(map_receiver(values, |values| {
+                values
+                    .into_iter()
+                    .map(|value| T::try_from(value).map_err(IdbError::from))
+                    .collect()
+            }))
+        }))
+    }
+    fn stream_store<T>(query: IdbQuery, mode: IdbCursorMode) -> IdbResult<IdbCursorStream<T>>
+    where
+        T: TryFrom<JsValue, Error = JsValue> + Unpin,
+    {
+        require_plaintext::<Self>()?;
+        Ok(IdbCursorStream::new(
+            Self::request_read_open()?,
+            query,
+            mode,
+        ))
+    }
+
+    fn request_store_open() -> IdbResult<Receiver<IdbResult<IdbObjectStore>>> {
+        let store_name = Self::store_name();
+        let store_names = match Self::cache_policy() {
+            Some(_) => vec![store_name, CACHE_STORE],
+            None => vec![store_name],
+        };
+        let (sender, receiver) = oneshot::channel();
+        spawn_local(async move {
+            let object_store = async {
+                open_transaction::<Self::Schema>(&store_names, IdbTransactionMode::Readwrite)
+                    .await?
+                    .object_store(store_name)
+                    .map_err(|_| IdbError::StoreMissing(store_name.to_string()))
+            }
+            .await;
+            let _ = sender.send(object_store);
+        });
+        Ok(receiver)
+    }
+
+    // Stores with a TTL retire what their policy no longer allows before a read opens,
+    // so reads never return expired records.
+    fn request_read_open() -> IdbResult<Receiver<IdbResult<IdbObjectStore>>> {
+        if Self::cache_policy().is_none_or(|policy| policy.ttl.is_none()) {
+            return Self::request_store_open();
+        }
+        let (sender, receiver) = oneshot::channel();
+        spawn_local(async move {
+            let object_store = async {
+                cache::enforce::<Self>().await?;
+                Self::request_store_open()?.await?
+            }
+            .await;
+            let _ = sender.send(object_store);
+        });
+        Ok(receiver)
+    }
+
+    fn request_db_open() -> IdbResult<Receiver<IdbResult<IdbDatabase>>> {
+        open_database::<Self::Schema>()
+    }
+}
+
+pub(super) fn require_plaintext<M: IdbStoreManager + ?Sized>() -> IdbResult<()> {
+    match M::encrypted() {
+        true => Err(IdbError::Transaction(format!(
+            "Store {} is encrypted; open it with IdbStore::encrypted",
+            M::store_name()
+        ))),
+        false => Ok(()),
+    }
+}
+
+fn put_record(
+    store: &IdbObjectStore,
+    inline_key: bool,
+    value: &JsValue,
+    key: &JsValue,
+) -> IdbResult<IdbRequest> {
+    let request = match inline_key {
+        true => store.put(value)?,
+        false => store.put_with_key(value, key)?,
+    };
+    Ok(request)
+}
@@ -0,0 +1,148 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+};
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{closure::Closure, JsCast};
+use web_sys::{BroadcastChannel, MessageEvent};
+use yew::Callback;
+
+use super::query::IdbKey;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "keys")]
+pub enum IdbChange {
+    Put(Vec<IdbKey>),
+    Delete(Vec<IdbKey>),
+    Clear,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IdbChangeEvent {
+    pub db_name: String,
+    pub store_name: String,
+    pub change: IdbChange,
+    #[serde(skip)]
+    pub remote: bool,
+}
+
+type StoreId = (String, String);
+type StoreListeners = HashMap<StoreId, Vec<(usize, Callback<IdbChangeEvent>)>>;
+
+struct BroadcastBridge {
+    channel: BroadcastChannel,
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+}
+
+thread_local! {
+    static LISTENERS: RefCell<StoreListeners> = RefCell::new(HashMap::new());
+    static NEXT_LISTENER: Cell<usize> = const { Cell::new(0) };
+    static BRIDGES: RefCell<HashMap<String, BroadcastBridge>> = RefCell::new(HashMap::new());
+}
+
+pub struct IdbObserver {
+    store: StoreId,
+    id: usize,
+}
+impl IdbObserver {
+    pub fn new(db_name: &str, store_name: &str, callback: Callback<IdbChangeEvent>) -> Self {
+        ensure_bridge(db_name);
+        let id = NEXT_LISTENER.with(|next| {
+            let id = next.get();
+            next.set(id + 1);
+            id
+        });
+        let store = (db_name.to_string(), store_name.to_string());
+        LISTENERS.with(|listeners| {
+            listeners
+                .borrow_mut()
+                .entry(store.clone())
+                .or_default()
+                .push((id, callback));
+        });
+        Self { store, id }
+    }
+}
+impl Drop for IdbObserver {
+    fn drop(&mut self) {
+        LISTENERS.with(|listeners| {
+            let mut listeners = listeners.borrow_mut();
+            if let Some(callbacks) = listeners.get_mut(&self.store) {
+                callbacks.retain(|(id, _)| *id != self.id);
+                if callbacks.is_empty() {
+                    listeners.remove(&self.store);
+                }
+            }
+        });
+    }
+}
+
+fn local_event(db_name: &str, store_name: &str, change: IdbChange) -> IdbChangeEvent {
+    IdbChangeEvent {
+        db_name: db_name.to_string(),
+        store_name: store_name.to_string(),
+        change,
+        remote: false,
+    }
+}
+
+pub fn notify_change(db_name: &str, store_name: &str, change: IdbChange) {
+    let event = local_event(db_name, store_name, change);
+    ensure_bridge(db_name);
+    BRIDGES.with(|bridges| {
+        if let Some(bridge) = bridges.borrow().get(db_name) {
+            if let Ok(message) = serde_wasm_bindgen::to_value(&event) {
+                let _ = bridge.channel.post_message(&message);
+            }
+        }
+    });
+    dispatch(event);
+}
+
+pub(super) fn notify_local(db_name: &str, store_name: &str, change: IdbChange) {
+    dispatch(local_event(db_name, store_name, change));
+}
+
+fn dispatch(event: IdbChangeEvent) {
+    let store = (event.db_name.clone(), event.store_name.clone());
+    let callbacks: Vec<Callback<IdbChangeEvent>> = LISTENERS.with(|listeners| {
+        listeners
+            .borrow()
+            .get(&store)
+            .map(|callbacks| callbacks.iter().map(|(_, cb)| cb.clone()).collect())
+            .unwrap_or_default()
+    });
+    for callback in callbacks {
+        callback.emit(event.clone());
+    }
+}
+
+fn ensure_bridge(db_name: &str) {
+    // Native builds (the in-memory backend) only notify listeners in this process.
+    if !cfg!(target_arch = "wasm32") {
+        return;
+    }
+    if BRIDGES.with(|bridges| bridges.borrow().contains_key(db_name)) {
+        return;
+    }
+    let Ok(channel) = BroadcastChannel::new(&format!("minions-idb:{}", db_name)) else {
+        return;
+    };
+    let on_message = Closure::<dyn FnMut(MessageEvent)>::new(move |message: MessageEvent| {
+        if let Ok(mut event) = serde_wasm_bindgen::from_value::<IdbChangeEvent>(message.data()) {
+            event.remote = true;
+            dispatch(event);
+        }
+    });
+    channel.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    BRIDGES.with(|bridges| {
+        bridges.borrow_mut().insert(
+            db_name.to_string(),
+            BroadcastBridge {
+                channel,
+                _on_message: on_message,
+            },
+        );
+    });
+}
@@ -0,0 +1,349 @@
+use std::{cell::RefCell, rc::Rc};
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use web_sys::{IdbCursorDirection, IdbCursorWithValue, IdbKeyRange, IdbObjectStore, IdbRequest};
+use yew::platform::{pinned::oneshot::Receiver, spawn_local};
+
+use super::{await_transaction, IdbError, IdbResult};
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum IdbKey {
+    Number(f64),
+    String(String),
+    Array(Vec<IdbKey>),
+}
+impl IdbKey {
+    pub fn to_js(&self) -> JsValue {
+        match self {
+            Self::Number(number) => JsValue::from_f64(*number),
+            Self::String(string) => JsValue::from_str(string),
+            Self::Array(keys) => keys
+                .iter()
+                .map(IdbKey::to_js)
+                .collect::<js_sys::Array>()
+                .into(),
+        }
+    }
+    pub fn from_js(value: &JsValue) -> IdbResult<Self> {
+        if let Some(number) = value.as_f64() {
+            return Ok(Self::Number(number));
+        }
+        if let Some(string) = value.as_string() {
+            return Ok(Self::String(string));
+        }
+        if let Some(array) = value.dyn_ref::<js_sys::Array>() {
+            return array
+                .iter()
+                .map(|key| Self::from_js(&key))
+                .collect::<IdbResult<Vec<_>>>()
+                .map(Self::Array);
+        }
+        Err(IdbError::Serialization(format!(
+            "Unsupported key: {:?}",
+            value
+        )))
+    }
+    pub fn from_json(value: &serde_json::Value) -> IdbResult<Self> {
+        serde_json::from_value(value.clone())
+            .map_err(|_| IdbError::Serialization(format!("Unsupported key: {}", value)))
+    }
+}
+impl From<&str> for IdbKey {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+impl From<String> for IdbKey {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+impl From<f64> for IdbKey {
+    fn from(value: f64) -> Self {
+        Self::Number(value)
+    }
+}
+impl From<u32> for IdbKey {
+    fn from(value: u32) -> Self {
+        Self::Number(value as f64)
+    }
+}
+impl From<u64> for IdbKey {
+    fn from(value: u64) -> Self {
+        Self::Number(value as f64)
+    }
+}
+impl From<i64> for IdbKey {
+    fn from(value: i64) -> Self {
+        Self::Number(value as f64)
+    }
+}
+impl From<Vec<IdbKey>> for IdbKey {
+    fn from(value: Vec<IdbKey>) -> Self {
+        Self::Array(value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IdbRange {
+    All,
+    Only(IdbKey),
+    LowerBound(IdbKey, bool),
+    UpperBound(IdbKey, bool),
+    Bound(IdbKey, IdbKey, bool, bool),
+    Prefix(IdbKey),
+}
+impl IdbRange {
+    pub fn only(key: impl Into<IdbKey>) -> Self {
+        Self::Only(key.into())
+    }
+    pub fn lower(key: impl Into<IdbKey>) -> Self {
+        Self::LowerBound(key.into(), false)
+    }
+    pub fn upper(key: impl Into<IdbKey>) -> Self {
+        Self::UpperBound(key.into(), false)
+    }
+    pub fn between(lower: impl Into<IdbKey>, upper: impl Into<IdbKey>) -> Self {
+        Self::Bound(lower.into(), upper.into(), false, false)
+    }
+    pub fn prefix(key: impl Into<IdbKey>) -> Self {
+        Self::Prefix(key.into())
+    }
+    // Array prefixes stop just below the successor of their last element, so keys
+    // continue past the prefix with elements of any type, nested arrays included.
+    fn prefix_range(prefix: &IdbKey) -> Self {
+        match prefix {
+            IdbKey::String(string) => Self::Bound(
+                prefix.clone(),
+                IdbKey::String(format!("{}\u{ffff}", string)),
+                false,
+                false,
+            ),
+            IdbKey::Array(keys) => match keys.split_last() {
+                Some((last, rest)) => {
+                    let mut upper = rest.to_vec();
+                    upper.push(successor(last));
+                    Self::Bound(prefix.clone(), IdbKey::Array(upper), false, true)
+                }
+                None => Self::LowerBound(prefix.clone(), false),
+            },
+            IdbKey::Number(_) => Self::Only(prefix.clone()),
+        }
+    }
+    pub fn contains(&self, key: &IdbKey) -> bool {
+        match self {
+            Self::All => true,
+            Self::Only(only) => key == only,
+            Self::LowerBound(lower, open) => above(key, lower, *open),
+            Self::UpperBound(upper, open) => above(upper, key, *open),
+            Self::Bound(lower, upper, lower_open, upper_open) => {
+                above(key, lower, *lower_open) && above(upper, key, *upper_open)
+            }
+            Self::Prefix(prefix) => Self::prefix_range(prefix).contains(key),
+        }
+    }
+    pub fn to_js(&self) -> IdbResult<JsValue> {
+        let range = match self {
+            Self::All => return Ok(JsValue::UNDEFINED),
+            Self::Only(key) => IdbKeyRange::only(&key.to_js())?,
+            Self::LowerBound(key, open) => IdbKeyRange::lower_bound_with_open(&key.to_js(), *open)?,
+            Self::UpperBound(key, open) => IdbKeyRange::upper_bound_with_open(&key.to_js(), *open)?,
+            Self::Bound(lower, upper, lower_open, upper_open) => {
+                IdbKeyRange::bound_with_lower_open_and_upper_open(
+                    &lower.to_js(),
+                    &upper.to_js(),
+                    *lower_open,
+                    *upper_open,
+                )?
+            }
+            Self::Prefix(prefix) => return Self::prefix_range(prefix).to_js(),
+        };
+        Ok(range.into())
+    }
+}
+
+// The smallest key above `key`; nothing sorts strictly between the two.
+fn successor(key: &IdbKey) -> IdbKey {
+    match key {
+        IdbKey::Number(number) if *number < f64::INFINITY => IdbKey::Number(number.next_up()),
+        IdbKey::Number(_) => IdbKey::String(String::new()),
+        IdbKey::String(string) => IdbKey::String(format!("{}\0", string)),
+        IdbKey::Array(keys) => {
+            let mut keys = keys.clone();
+            keys.push(IdbKey::Number(f64::NEG_INFINITY));
+            IdbKey::Array(keys)
+        }
+    }
+}
+
+fn above(key: &IdbKey, bound: &IdbKey, open: bool) -> bool {
+    match open {
+        true => key > bound,
+        false => key >= bound,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdbDirection {
+    #[default]
+    Next,
+    NextUnique,
+    Prev,
+    PrevUnique,
+}
+impl From<IdbDirection> for IdbCursorDirection {
+    fn from(value: IdbDirection) -> Self {
+        match value {
+            IdbDirection::Next => IdbCursorDirection::Next,
+            IdbDirection::NextUnique => IdbCursorDirection::Nextunique,
+            IdbDirection::Prev => IdbCursorDirection::Prev,
+            IdbDirection::PrevUnique => IdbCursorDirection::Prevunique,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdbQuery {
+    pub index: Option<&'static str>,
+    pub range: IdbRange,
+    pub direction: IdbDirection,
+    pub limit: Option<u32>,
+    pub offset: u32,
+}
+impl Default for IdbQuery {
+    fn default() -> Self {
+        Self {
+            index: None,
+            range: IdbRange::All,
+            direction: IdbDirection::Next,
+            limit: None,
+            offset: 0,
+        }
+    }
+}
+impl IdbQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn index(mut self, index: &'static str) -> Self {
+        self.index = Some(index);
+        self
+    }
+    pub fn range(mut self, range: IdbRange) -> Self {
+        self.range = range;
+        self
+    }
+    pub fn direction(mut self, direction: IdbDirection) -> Self {
+        self.direction = direction;
+        self
+    }
+    pub fn reverse(self) -> Self {
+        self.direction(IdbDirection::Prev)
+    }
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+    pub fn offset(mut self, offset: u32) -> Self {
+        self.offset = offset;
+        self
+    }
+    pub(super) fn open_cursor(
+        &self,
+        store: &IdbObjectStore,
+        key_only: bool,
+    ) -> IdbResult<IdbRequest> {
+        let range = self.range.to_js()?;
+        let direction = self.direction.into();
+        let request = match (self.index, key_only) {
+            (Some(index), key_only) => {
+                let index = store
+                    .index(index)
+                    .map_err(|_| IdbError::StoreMissing(format!("{}.{}", store.name(), index)))?;
+                match key_only {
+                    true => index.open_key_cursor_with_range_and_direction(&range, direction)?,
+                    false => index.open_cursor_with_range_and_direction(&range, direction)?,
+                }
+            }
+            (None, true) => store.open_key_cursor_with_range_and_direction(&range, direction)?,
+            (None, false) => store.open_cursor_with_range_and_direction(&range, direction)?,
+        };
+        Ok(request)
+    }
+    pub(super) fn collect(
+        &self,
+        store: &IdbObjectStore,
+    ) -> IdbResult<Receiver<IdbResult<Vec<JsValue>>>> {
+        let request = self.open_cursor(store, false)?;
+        let values = Rc::new(RefCell::new(Vec::new()));
+        let receiver = await_transaction(&store.transaction(), values.clone());
+        let mut skip = self.offset;
+        let limit = self.limit.map(|limit| limit as usize);
+        let cursor_request = request.clone();
+        let on_success = Closure::<dyn FnMut(web_sys::Event)>::new(move |_: web_sys::Event| {
+            let Some(cursor) = cursor_request
+                .result()
+                .ok()
+                .and_then(|cursor| cursor.dyn_into::<IdbCursorWithValue>().ok())
+            else {
+                return;
+            };
+            if skip > 0 {
+                let _ = cursor.advance(skip);
+                skip = 0;
+                return;
+            }
+            if !has_room(limit, values.borrow().len()) {
+                return;
+            }
+            if let Ok(value) = cursor.value() {
+                values.borrow_mut().push(value);
+            }
+            if has_room(limit, values.borrow().len()) {
+                let _ = cursor.continue_();
+            }
+        });
+        request.set_onsuccess(Some(on_success.as_ref().unchecked_ref()));
+        let (sender, result) = yew::platform::pinned::oneshot::channel();
+        spawn_local(async move {
+            let values = receiver
+                .await
+                .map_err(IdbError::from)
+                .and_then(|values| values);
+            drop(on_success);
+            let _ = sender.send(values);
+        });
+        Ok(result)
+    }
+}
+
+// Checked before reading a record too, so a zero limit returns nothing.
+fn has_room(limit: Option<usize>, collected: usize) -> bool {
+    limit.is_none_or(|limit| collected < limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{has_room, IdbKey, IdbRange};
+
+    #[test]
+    fn array_prefix_covers_every_key_type_after_it() {
+        let range = IdbRange::prefix(vec![IdbKey::from("notes")]);
+        let key = |rest: IdbKey| IdbKey::Array(vec![IdbKey::from("notes"), rest]);
+        assert!(range.contains(&key(IdbKey::from(1u32))));
+        assert!(range.contains(&key(IdbKey::from("id"))));
+        assert!(range.contains(&key(IdbKey::Array(vec![IdbKey::from("nested")]))));
+        assert!(!range.contains(&IdbKey::Array(vec![IdbKey::from("notes\0")])));
+        assert!(!range.contains(&IdbKey::Array(vec![IdbKey::from("notesx")])));
+    }
+
+    #[test]
+    fn zero_limit_collects_nothing() {
+        assert!(!has_room(Some(0), 0));
+        assert!(has_room(Some(2), 1));
+        assert!(!has_room(Some(2), 2));
+        assert!(has_room(None, 100));
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+
+use wasm_bindgen::JsValue;
+use web_sys::{
+    IdbDatabase, IdbIndexParameters, IdbObjectStore, IdbObjectStoreParameters, IdbOpenDbRequest,
+    IdbTransaction, IdbVersionChangeEvent,
+};
+
+use super::{IdbError, IdbResult};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdbIndexSchema {
+    pub name: &'static str,
+    pub key_path: Vec<&'static str>,
+    pub unique: bool,
+    pub multi_entry: bool,
+}
+impl IdbIndexSchema {
+    pub fn new(name: &'static str, key_path: &'static str) -> Self {
+        Self::compound(name, &[key_path])
+    }
+    pub fn compound(name: &'static str, key_path: &[&'static str]) -> Self {
+        Self {
+            name,
+            key_path: key_path.to_vec(),
+            unique: false,
+            multi_entry: false,
+        }
+    }
+    pub fn unique(mut self) -> Self {
+        self.unique = true;
+        self
+    }
+    pub fn multi_entry(mut self) -> Self {
+        self.multi_entry = true;
+        self
+    }
+    fn create(&self, store: &IdbObjectStore) -> IdbResult<()> {
+        let params = IdbIndexParameters::new();
+        params.set_unique(self.unique);
+        params.set_multi_entry(self.multi_entry);
+        match self.key_path.as_slice() {
+            [key_path] => {
+                store.create_index_with_str_and_optional_parameters(self.name, key_path, &params)?
+            }
+            key_paths => {
+                let key_paths: js_sys::Array = key_paths
+                    .iter()
+                    .map(|path| JsValue::from_str(path))
+                    .collect();
+                store.create_index_with_str_sequence_and_optional_parameters(
+                    self.name, &key_paths, &params,
+                )?
+            }
+        };
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdbStoreSchema {
+    pub name: &'static str,
+    pub key_path: Option<&'static str>,
+    pub auto_increment: bool,
+    pub indexes: Vec<IdbIndexSchema>,
+}
+impl IdbStoreSchema {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            key_path: None,
+            auto_increment: false,
+            indexes: Vec::new(),
+        }
+    }
+    pub fn key_path(mut self, key_path: &'static str) -> Self {
+        self.key_path = Some(key_path);
+        self
+    }
+    pub fn auto_increment(mut self) -> Self {
+        self.auto_increment = true;
+        self
+    }
+    pub fn index(mut self, index: IdbIndexSchema) -> Self {
+        self.indexes.push(index);
+        self
+    }
+    fn create(&self, db: &IdbDatabase, transaction: &IdbTransaction) -> IdbResult<()> {
+        let store = if db.object_store_names().contains(self.name) {
+            transaction.object_store(self.name)?
+        } else {
+            let params = IdbObjectStoreParameters::new();
+            params.set_auto_increment(self.auto_increment);
+            if let Some(key_path) = self.key_path {
+                params.set_key_path(&JsValue::from_str(key_path));
+            }
+            db.create_object_store_with_optional_parameters(self.name, &params)?
+        };
+        for index in &self.indexes {
+            if !store.index_names().contains(index.name) {
+                index.create(&store)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub type IdbMigrationFn = fn(&IdbDatabase, &IdbTransaction) -> IdbResult<()>;
+
+#[derive(Clone)]
+pub enum IdbMigrationStep {
+    CreateStore(IdbStoreSchema),
+    DeleteStore(&'static str),
+    CreateIndex(&'static str, IdbIndexSchema),
+    DeleteIndex(&'static str, &'static str),
+    Custom(IdbMigrationFn),
+}
+impl IdbMigrationStep {
+    fn apply(&self, db: &IdbDatabase, transaction: &IdbTransaction) -> IdbResult<()> {
+        match self {
+            Self::CreateStore(store) => store.create(db, transaction),
+            Self::DeleteStore(name) => {
+                if db.object_store_names().contains(name) {
+                    db.delete_object_store(name)?;
+                }
+                Ok(())
+            }
+            Self::CreateIndex(store_name, index) => {
+                let store = Self::existing_store(db, transaction, store_name)?;
+                if !store.index_names().contains(index.name) {
+                    index.create(&store)?;
+                }
+                Ok(())
+            }
+            Self::DeleteIndex(store_name, index_name) => {
+                let store = Self::existing_store(db, transaction, store_name)?;
+                if store.index_names().contains(index_name) {
+                    store.delete_index(index_name)?;
+                }
+                Ok(())
+            }
+            Self::Custom(migration) => migration(db, transaction),
+        }
+    }
+    fn existing_store(
+        db: &IdbDatabase,
+        transaction: &IdbTransaction,
+        store_name: &str,
+    ) -> IdbResult<IdbObjectStore> {
+        if !db.object_store_names().contains(store_name) {
+            return Err(IdbError::StoreMissing(store_name.to_string()));
+        }
+        Ok(transaction.object_store(store_name)?)
+    }
+}
+
+#[derive(Clone)]
+pub struct IdbMigration {
+    pub version: u32,
+    pub steps: Vec<IdbMigrationStep>,
+}
+impl IdbMigration {
+    pub fn new(version: u32) -> Self {
+        Self {
+            version,
+            steps: Vec::new(),
+        }
+    }
+    pub fn create_store(mut self, store: IdbStoreSchema) -> Self {
+        self.steps.push(IdbMigrationStep::CreateStore(store));
+        self
+    }
+    pub fn delete_store(mut self, store_name: &'static str) -> Self {
+        self.steps.push(IdbMigrationStep::DeleteStore(store_name));
+        self
+    }
+    pub fn create_index(mut self, store_name: &'static str, index: IdbIndexSchema) -> Self {
+        self.steps
+            .push(IdbMigrationStep::CreateIndex(store_name, index));
+        self
+    }
+    pub fn delete_index(mut self, store_name: &'static str, index_name: &'static str) -> Self {
+        self.steps
+            .push(IdbMigrationStep::DeleteIndex(store_name, index_name));
+        self
+    }
+    pub fn custom(mut self, migration: IdbMigrationFn) -> Self {
+        self.steps.push(IdbMigrationStep::Custom(migration));
+        self
+    }
+}
+
+pub trait IdbSchema: 'static {
+    fn db_name() -> &'static str;
+    fn migrations() -> Vec<IdbMigration>;
+    fn db_version() -> u32 {
+        Self::migrations()
+            .iter()
+            .map(|migration| migration.version)
+            .max()
+            .unwrap_or(1)
+    }
+    fn store_schemas() -> HashMap<&'static str, IdbStoreSchema> {
+        let mut migrations = Self::migrations();
+        migrations.sort_by_key(|migration| migration.version);
+        let mut stores: HashMap<&'static str, IdbStoreSchema> = HashMap::new();
+        for step in migrations
+            .iter()
+            .flat_map(|migration| migration.steps.iter())
+        {
+            match step {
+                IdbMigrationStep::CreateStore(store) => match stores.get_mut(store.name) {
+                    Some(existing) => {
+                        for index in &store.indexes {
+                            if !existing.indexes.iter().any(|i| i.name == index.name) {
+                                existing.indexes.push(index.clone());
+                            }
+                        }
+                    }
+                    None => {
+                        stores.insert(store.name, store.clone());
+                    }
+                },
+                IdbMigrationStep::DeleteStore(name) => {
+                    stores.remove(name);
+                }
+                IdbMigrationStep::CreateIndex(store_name, index) => {
+                    if let Some(store) = stores.get_mut(store_name) {
+                        if !store.indexes.iter().any(|i| i.name == index.name) {
+                            store.indexes.push(index.clone());
+                        }
+                    }
+                }
+                IdbMigrationStep::DeleteIndex(store_name, index_name) => {
+                    if let Some(store) = stores.get_mut(store_name) {
+                        store.indexes.retain(|index| index.name != *index_name);
+                    }
+                }
+                IdbMigrationStep::Custom(_) => {}
+            }
+        }
+        stores
+    }
+    fn upgrade(request: &IdbOpenDbRequest, event: &IdbVersionChangeEvent) -> IdbResult<()> {
+        let db: IdbDatabase = request.result()?.into();
+        let transaction = request
+            .transaction()
+            .ok_or(IdbError::VersionError("No upgrade transaction".to_string()))?;
+        let old_version = event.old_version() as u32;
+        let new_version = event
+            .new_version()
+            .map(|version| version as u32)
+            .unwrap_or_else(Self::db_version);
+        let mut migrations = Self::migrations();
+        migrations.sort_by_key(|migration| migration.version);
+        migrations
+            .iter()
+            .filter(|migration| migration.version > old_version && migration.version <= new_version)
+            .flat_map(|migration| migration.steps.iter())
+            .try_for_each(|step| step.apply(&db, &transaction))
+    }
+}
@@ -0,0 +1,182 @@
+use std::{cell::RefCell, collections::HashMap, fmt, marker::PhantomData, rc::Rc};
+
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use web_sys::{IdbObjectStore, IdbRequest, IdbTransaction, IdbTransactionMode};
+use yew::platform::pinned::oneshot::{self, Receiver};
+
+use super::{
+    await_transaction,
+    cache::{record_access, record_delete, record_write, spawn_enforce, CACHE_STORE},
+    connection::open_transaction,
+    observer::{notify_change, IdbChange},
+    put_record,
+    query::IdbKey,
+    schema::IdbSchema,
+    IdbError, IdbResult, IdbStoreManager, SharedSender,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdbAccess {
+    ReadOnly,
+    ReadWrite,
+}
+impl From<IdbAccess> for IdbTransactionMode {
+    fn from(value: IdbAccess) -> Self {
+        match value {
+            IdbAccess::ReadOnly => IdbTransactionMode::Readonly,
+            IdbAccess::ReadWrite => IdbTransactionMode::Readwrite,
+        }
+    }
+}
+
+pub struct IdbStoreTransaction<S: IdbSchema> {
+    transaction: IdbTransaction,
+    completion: Receiver<IdbResult<()>>,
+    changes: RefCell<Vec<(&'static str, IdbChange)>>,
+    enforce: RefCell<HashMap<&'static str, fn()>>,
+    _schema: PhantomData<S>,
+}
+impl<S: IdbSchema> IdbStoreTransaction<S> {
+    // Cache bookkeeping joins every transaction of a schema that registers it, so
+    // writes to cached stores stay in step with their entries.
+    pub async fn open(store_names: &[&str], access: IdbAccess) -> IdbResult<Self> {
+        let mut store_names = store_names.to_vec();
+        if S::store_schemas().contains_key(CACHE_STORE) && !store_names.contains(&CACHE_STORE) {
+            store_names.push(CACHE_STORE);
+        }
+        let transaction = open_transaction::<S>(&store_names, access.into()).await?;
+        let completion = await_transaction(&transaction, Rc::new(RefCell::new(())));
+        Ok(Self {
+            transaction,
+            completion,
+            changes: RefCell::new(Vec::new()),
+            enforce: RefCell::new(HashMap::new()),
+            _schema: PhantomData,
+        })
+    }
+    fn object_store<M: IdbStoreManager>(&self) -> IdbResult<IdbObjectStore> {
+        self.transaction
+            .object_store(M::store_name())
+            .map_err(|_| IdbError::StoreMissing(M::store_name().to_string()))
+    }
+    pub fn put<M>(&self, record: M) -> IdbResult<()>
+    where
+        M: IdbStoreManager<Schema = S> + TryInto<JsValue, Error = JsValue>,
+    {
+        let store = self.object_store::<M>()?;
+        let key = record.document_key();
+        let value: JsValue = record.try_into()?;
+        let inline_key = M::store_schema().key_path.is_some();
+        put_record(&store, inline_key, &value, &key.to_js())?;
+        self.record_cache_write::<M>(&store, &key, &value)?;
+        self.record_change::<M>(IdbChange::Put(vec![key]));
+        Ok(())
+    }
+    pub fn put_value<M>(&self, value: JsValue, key: &str) -> IdbResult<()>
+    where
+        M: IdbStoreManager<Schema = S>,
+    {
+        let key = IdbKey::from(key);
+        let store = self.object_store::<M>()?;
+        let inline_key = M::store_schema().key_path.is_some();
+        put_record(&store, inline_key, &value, &key.to_js())?;
+        self.record_cache_write::<M>(&store, &key, &value)?;
+        self.record_change::<M>(IdbChange::Put(vec![key]));
+        Ok(())
+    }
+    pub fn delete<M>(&self, record: &M) -> IdbResult<()>
+    where
+        M: IdbStoreManager<Schema = S>,
+    {
+        let key = record.document_key();
+        let store = self.object_store::<M>()?;
+        store.delete(&key.to_js())?;
+        if M::cache_policy().is_some() {
+            record_delete(&store, &key)?;
+        }
+        self.record_change::<M>(IdbChange::Delete(vec![key]));
+        Ok(())
+    }
+    pub fn get<M, T>(&self, key: &str) -> IdbResult<Receiver<IdbResult<Option<T>>>>
+    where
+        M: IdbStoreManager<Schema = S>,
+        T: TryFrom<JsValue> + 'static,
+        T::Error: fmt::Debug,
+    {
+        let store = self.object_store::<M>()?;
+        let request = store.get(&JsValue::from_str(key))?;
+        let expired = match M::cache_policy() {
+            Some(policy) => Some(record_access(&store, &IdbKey::from(key), policy)?),
+            None => None,
+        };
+        Ok(request_value(&request, move |value| {
+            let expired = expired.is_some_and(|expired| expired.get());
+            if value.is_null() || value.is_undefined() || expired {
+                return Ok(None);
+            }
+            T::try_from(value)
+                .map(Some)
+                .map_err(|e| IdbError::Serialization(format!("{:?}", e)))
+        }))
+    }
+    pub fn abort(self) -> IdbResult<()> {
+        self.transaction.abort()?;
+        Ok(())
+    }
+    pub async fn commit(self) -> IdbResult<()> {
+        // Older browsers lack `commit()` and rely on auto-commit instead.
+        if let Ok(commit) = js_sys::Reflect::get(&self.transaction, &"commit".into()) {
+            if let Some(commit) = commit.dyn_ref::<js_sys::Function>() {
+                let _ = commit.call0(&self.transaction);
+            }
+        }
+        self.completion.await??;
+        for (store_name, change) in self.changes.take() {
+            notify_change(S::db_name(), store_name, change);
+        }
+        for enforce in self.enforce.take().into_values() {
+            enforce();
+        }
+        Ok(())
+    }
+    fn record_cache_write<M: IdbStoreManager>(
+        &self,
+        store: &IdbObjectStore,
+        key: &IdbKey,
+        value: &JsValue,
+    ) -> IdbResult<()> {
+        if M::cache_policy().is_none() {
+            return Ok(());
+        }
+        record_write(store, key, value)?;
+        self.enforce
+            .borrow_mut()
+            .insert(M::store_name(), spawn_enforce::<M>);
+        Ok(())
+    }
+    fn record_change<M: IdbStoreManager>(&self, change: IdbChange) {
+        self.changes.borrow_mut().push((M::store_name(), change));
+    }
+}
+
+fn request_value<T, F>(request: &IdbRequest, map: F) -> Receiver<IdbResult<T>>
+where
+    T: 'static,
+    F: FnOnce(JsValue) -> IdbResult<T> + 'static,
+{
+    let (sender, receiver) = oneshot::channel();
+    let sender = SharedSender::new(sender);
+    let success_sender = sender.clone();
+    let success_request = request.clone();
+    let on_success = Closure::once_into_js(move |_: web_sys::Event| {
+        let value = success_request.result().map_err(IdbError::from);
+        success_sender.send(value.and_then(map));
+    });
+    let error_request = request.clone();
+    let on_error = Closure::once_into_js(move |_: web_sys::Event| {
+        sender.send(Err(IdbError::from_request(&error_request)));
+    });
+    request.set_onsuccess(Some(on_success.unchecked_ref()));
+    request.set_onerror(Some(on_error.unchecked_ref()));
+    receiver
+}
@@ -1,4 +1,5 @@
-use wasm_bindgen::JsValue;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
 
 pub struct AppServiceWorker {
     sw: web_sys::ServiceWorkerContainer,
@@ -15,6 +16,17 @@ impl AppServiceWorker {
         gloo::console::info!(register);
         Ok(())
     }
+    // Background sync is not in web-sys' stable API, so the registration is driven
+    // through Reflect.
+    pub async fn register_sync(&self, tag: &str) -> Result<(), JsValue> {
+        let registration = JsFuture::from(self.sw.ready()?).await?;
+        let sync = js_sys::Reflect::get(&registration, &"sync".into())?;
+        if sync.is_undefined() {
+            return Err(JsValue::from_str("Background sync not supported"));
+        }
+        let register: js_sys::Function =
+            js_sys::Reflect::get(&sync, &"register".into())?.dyn_into()?;
+        JsFuture::from(js_sys::Promise::from(register.call1(&sync, &tag.into())?)).await?;
+        Ok(())
+    }
 }
-
-
@@ -2,8 +2,11 @@ use nostro2::userkeys::UserKeys;
 use wasm_bindgen::JsValue;
 use web_sys::CryptoKey;
 
-use crate::browser_api::{crypto::{crypto_to_user_keys, user_keys_to_crypto}, indexed_db::IdbStoreManager};
-
+use crate::browser_api::{
+    crypto::{crypto_to_user_keys, user_keys_to_crypto},
+    indexed_db::{encrypted::IdbCipher, query::IdbKey, IdbError, IdbStoreManager},
+};
+use crate::nostr_db::NostrDb;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct UserIdentity {
@@ -18,7 +21,8 @@ impl UserIdentity {
     {
         let crypto_key = Self::retrieve::<CryptoKey>("privateKey")?
             .await
-            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+            .map_err(|e| JsValue::from_str(&e.to_string()))??
+            .ok_or(IdbError::NotFound)?;
         Ok(UserIdentity {
             id: "privateKey".to_string(),
             crypto_key,
@@ -27,9 +31,9 @@ impl UserIdentity {
     pub async fn new_user_identity() -> Result<Self, JsValue> {
         let user_key = UserKeys::generate_extractable();
         let crypto_key: CryptoKey = user_keys_to_crypto(&user_key).await?.into();
-        if let Err(e) = Self::save_value_to_store(crypto_key.clone().into(), "privateKey")?.await {
-            gloo::console::error!("Error saving key: ", format!("{:?}", e));
-        }
+        Self::save_value_to_store(crypto_key.clone().into(), "privateKey")?
+            .await
+            .map_err(|e| JsValue::from_str(&e.to_string()))??;
         Ok(Self {
             id: "privateKey".to_string(),
             crypto_key,
@@ -37,9 +41,9 @@ impl UserIdentity {
     }
     pub async fn from_new_keys(keys: UserKeys) -> Result<Self, JsValue> {
         let crypto_key: CryptoKey = user_keys_to_crypto(&keys).await?.into();
-        if let Err(e) = Self::save_value_to_store(crypto_key.clone().into(), "privateKey")?.await {
-            gloo::console::error!("Error saving key: ", format!("{:?}", e));
-        }
+        Self::save_value_to_store(crypto_key.clone().into(), "privateKey")?
+            .await
+            .map_err(|e| JsValue::from_str(&e.to_string()))??;
         Ok(UserIdentity {
             id: "privateKey".to_string(),
             crypto_key,
@@ -51,23 +55,18 @@ impl UserIdentity {
     pub fn get_id(&self) -> String {
         self.id.clone()
     }
+    pub async fn storage_cipher(&self) -> Result<IdbCipher, JsValue> {
+        let user_keys = self.get_user_keys().await?;
+        Ok(IdbCipher::from_user_keys(&user_keys).await?)
+    }
 }
 
 impl IdbStoreManager for UserIdentity {
+    type Schema = NostrDb;
     fn store_name() -> &'static str {
         "user_identity"
     }
-    fn db_name() -> &'static str {
-        "nostr"
-    }
-    fn db_version() -> u32 {
-        1
-    }
-    fn document_key(&self) -> JsValue {
-        JsValue::from_str(&self.id)
-    }
-    fn upgrade_db(event: web_sys::Event) -> Result<(), JsValue> {
-        Ok(())
+    fn document_key(&self) -> IdbKey {
+        IdbKey::from(self.id.as_str())
     }
 }
-
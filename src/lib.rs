@@ -1,6 +1,6 @@
 pub mod browser_api;
 pub mod key_manager;
+pub mod nostr_db;
 pub mod relay_pool;
 pub mod router;
 pub mod widgets;
-
@@ -0,0 +1,89 @@
+use crate::browser_api::indexed_db::{
+    cache::CACHE_STORE,
+    schema::{IdbIndexSchema, IdbMigration, IdbSchema, IdbStoreSchema},
+    IdbStoreManager,
+};
+use crate::key_manager::nostr_id::UserIdentity;
+use crate::relay_pool::{
+    nostr_relay::UserRelay,
+    note_store::{
+        StoredNote, ADDRESS_INDEX, CREATED_AT_INDEX, KIND_INDEX, PUBKEY_INDEX, TAG_INDEX,
+    },
+    outbox::OutboxEntry,
+};
+
+pub struct NostrDb;
+impl IdbSchema for NostrDb {
+    fn db_name() -> &'static str {
+        "nostr"
+    }
+    fn migrations() -> Vec<IdbMigration> {
+        // Version 1 databases were created without any object stores. Each version
+        // spells out its own stores and indexes so later changes to a model's schema
+        // cannot rewrite history.
+        vec![
+            IdbMigration::new(2)
+                .create_store(IdbStoreSchema::new(UserRelay::store_name()))
+                .create_store(IdbStoreSchema::new(UserIdentity::store_name())),
+            IdbMigration::new(3).create_store(
+                IdbStoreSchema::new(StoredNote::store_name())
+                    .key_path("id")
+                    .index(IdbIndexSchema::compound(
+                        CREATED_AT_INDEX,
+                        &["created_at", "id"],
+                    )),
+            ),
+            IdbMigration::new(4).create_index(
+                StoredNote::store_name(),
+                IdbIndexSchema::new(ADDRESS_INDEX, "address"),
+            ),
+            IdbMigration::new(5)
+                .create_store(IdbStoreSchema::new(OutboxEntry::store_name()).key_path("id")),
+            IdbMigration::new(6)
+                .create_index(
+                    StoredNote::store_name(),
+                    IdbIndexSchema::new(PUBKEY_INDEX, "pubkey"),
+                )
+                .create_index(
+                    StoredNote::store_name(),
+                    IdbIndexSchema::new(KIND_INDEX, "kind"),
+                )
+                .create_index(
+                    StoredNote::store_name(),
+                    IdbIndexSchema::new(TAG_INDEX, "tags").multi_entry(),
+                ),
+            IdbMigration::new(7).create_store(IdbStoreSchema::new(CACHE_STORE)),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NostrDb;
+    use crate::browser_api::indexed_db::{
+        cache::cache_store_schema,
+        schema::{IdbSchema, IdbStoreSchema},
+        IdbStoreManager,
+    };
+    use crate::key_manager::nostr_id::UserIdentity;
+    use crate::relay_pool::{nostr_relay::UserRelay, note_store::StoredNote, outbox::OutboxEntry};
+
+    #[test]
+    fn migrations_build_the_current_schemas() {
+        let mut migrated = NostrDb::store_schemas();
+        let current: Vec<IdbStoreSchema> = vec![
+            UserRelay::store_schema(),
+            UserIdentity::store_schema(),
+            StoredNote::store_schema(),
+            OutboxEntry::store_schema(),
+            cache_store_schema(),
+        ];
+        assert_eq!(migrated.len(), current.len());
+        for mut current in current {
+            let mut migrated = migrated.remove(current.name).unwrap();
+            migrated.indexes.sort_by_key(|index| index.name);
+            current.indexes.sort_by_key(|index| index.name);
+            assert_eq!(migrated, current);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::{ops::Deref, rc::Rc};
+
+use im_rc::Vector;
+
+pub const DEFAULT_EVENT_CAPACITY: usize = 1000;
+pub const DEFAULT_NOTE_CAPACITY: usize = 500;
+
+// Snapshots compare by pointer so context consumers only re-render when the buffer
+// behind them actually changed.
+pub struct BufferSnapshot<T>(Rc<Vector<T>>);
+impl<T> Clone for BufferSnapshot<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+impl<T: Clone> Default for BufferSnapshot<T> {
+    fn default() -> Self {
+        Self(Rc::new(Vector::new()))
+    }
+}
+impl<T: Clone + std::fmt::Debug> std::fmt::Debug for BufferSnapshot<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("BufferSnapshot").field(&self.0).finish()
+    }
+}
+impl<T> PartialEq for BufferSnapshot<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+impl<T> Deref for BufferSnapshot<T> {
+    type Target = Vector<T>;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+// The rest of the pool state reaches consumers the same way: cloning shares it,
+// mutating copies it only while a consumer still holds the old version.
+#[derive(Debug, Default)]
+pub struct Shared<T>(Rc<T>);
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+impl<T> PartialEq for Shared<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+impl<T> Deref for Shared<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+impl<T: Clone> Shared<T> {
+    pub fn new(value: T) -> Self {
+        Self(Rc::new(value))
+    }
+    pub(super) fn make_mut(&mut self) -> &mut T {
+        Rc::make_mut(&mut self.0)
+    }
+}
+
+// Copy-on-write over a persistent vector: pushing while a snapshot is alive copies
+// the touched chunk, not the whole buffer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RingBuffer<T: Clone> {
+    capacity: usize,
+    items: BufferSnapshot<T>,
+}
+impl<T: Clone> RingBuffer<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            items: BufferSnapshot::default(),
+        }
+    }
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+    pub fn push(&mut self, item: T) -> Vec<T> {
+        Rc::make_mut(&mut self.items.0).push_back(item);
+        self.trim()
+    }
+    pub fn set_capacity(&mut self, capacity: usize) -> Vec<T> {
+        self.capacity = capacity;
+        self.trim()
+    }
+    pub fn retain(&mut self, keep: impl FnMut(&T) -> bool) {
+        Rc::make_mut(&mut self.items.0).retain(keep);
+    }
+    pub fn snapshot(&self) -> BufferSnapshot<T> {
+        self.items.clone()
+    }
+    fn trim(&mut self) -> Vec<T> {
+        let overflow = self.items.len().saturating_sub(self.capacity);
+        if overflow == 0 {
+            return Vec::new();
+        }
+        Rc::make_mut(&mut self.items.0)
+            .slice(..overflow)
+            .into_iter()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RingBuffer;
+
+    #[test]
+    fn ring_buffer_evicts_oldest_and_keeps_snapshots() {
+        let mut buffer = RingBuffer::new(3);
+        for item in 0..3 {
+            assert!(buffer.push(item).is_empty());
+        }
+        let snapshot = buffer.snapshot();
+        assert_eq!(buffer.push(3), vec![0]);
+        assert_eq!(snapshot.iter().copied().collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(
+            buffer.snapshot().iter().copied().collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_ne!(snapshot, buffer.snapshot());
+        assert_eq!(buffer.set_capacity(1), vec![1, 2]);
+        assert_eq!(buffer.len(), 1);
+    }
+}
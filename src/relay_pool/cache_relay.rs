@@ -0,0 +1,183 @@
+use std::collections::{HashMap, HashSet};
+
+use nostro2::{notes::SignedNote, relays::NostrSubscription};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::browser_api::indexed_db::{
+    backend::{IdbBackend, IdbStore},
+    query::{IdbKey, IdbQuery, IdbRange},
+    IdbResult,
+};
+
+use super::note_store::{
+    tag_key, StoredNote, CREATED_AT_INDEX, KIND_INDEX, PUBKEY_INDEX, TAG_INDEX,
+};
+use super::replaceable::supersedes;
+
+pub const CACHE_RELAY_URL: &str = "cache://indexeddb";
+
+// nostro2 keeps filter fields private and skips tags when serializing, so the filter
+// is read back from the REQ message the relays receive.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+pub struct CacheFilter {
+    pub ids: Option<Vec<String>>,
+    pub authors: Option<Vec<String>>,
+    pub kinds: Option<Vec<u32>>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    pub limit: Option<u32>,
+    #[serde(flatten)]
+    extra: HashMap<String, Value>,
+}
+impl CacheFilter {
+    pub fn from_subscription(subscription: &NostrSubscription) -> Option<Self> {
+        let message = subscription.nostr_message().into_text().ok()?;
+        Self::from_req(&message)
+    }
+    pub fn from_req(message: &str) -> Option<Self> {
+        let (_, _, filter): (String, String, Self) = serde_json::from_str(message).ok()?;
+        Some(filter)
+    }
+    pub fn tags(&self) -> Vec<(&str, Vec<&str>)> {
+        self.extra
+            .iter()
+            .filter_map(|(key, values)| {
+                let name = key.strip_prefix('#')?;
+                let values = values
+                    .as_array()?
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .collect();
+                Some((name, values))
+            })
+            .collect()
+    }
+    pub fn matches(&self, note: &SignedNote) -> bool {
+        let contains = |values: &Option<Vec<String>>, value: &str| {
+            values
+                .as_ref()
+                .is_none_or(|values| values.iter().any(|v| v == value))
+        };
+        contains(&self.ids, note.get_id())
+            && contains(&self.authors, note.get_pubkey())
+            && self
+                .kinds
+                .as_ref()
+                .is_none_or(|kinds| kinds.contains(&note.get_kind()))
+            && self
+                .since
+                .is_none_or(|since| note.get_created_at() >= since)
+            && self
+                .until
+                .is_none_or(|until| note.get_created_at() <= until)
+            && self.tags().iter().all(|(name, values)| {
+                note.get_tags().iter().any(|tag| match tag.as_slice() {
+                    [tag_name, value, ..] => tag_name == name && values.contains(&value.as_str()),
+                    _ => false,
+                })
+            })
+    }
+    // The narrowest indexed field picks the candidates; the rest of the filter is
+    // applied in memory.
+    fn candidate_queries(&self) -> Vec<IdbQuery> {
+        let only = |index: &'static str, key: IdbKey| {
+            IdbQuery::new().index(index).range(IdbRange::Only(key))
+        };
+        if let Some(ids) = &self.ids {
+            return ids
+                .iter()
+                .map(|id| IdbQuery::new().range(IdbRange::only(id.as_str())))
+                .collect();
+        }
+        if let Some(authors) = &self.authors {
+            return authors
+                .iter()
+                .map(|author| only(PUBKEY_INDEX, IdbKey::from(author.as_str())))
+                .collect();
+        }
+        if let Some(kinds) = &self.kinds {
+            return kinds
+                .iter()
+                .map(|kind| only(KIND_INDEX, IdbKey::from(*kind)))
+                .collect();
+        }
+        if let Some((name, values)) = self.tags().into_iter().next() {
+            return values
+                .iter()
+                .map(|value| only(TAG_INDEX, IdbKey::from(tag_key(name, value))))
+                .collect();
+        }
+        let lower = IdbKey::Array(vec![IdbKey::from(self.since.unwrap_or(0))]);
+        let range = match self.until {
+            Some(until) => IdbRange::Bound(
+                lower,
+                IdbKey::Array(vec![IdbKey::from(until), IdbKey::Array(vec![])]),
+                false,
+                false,
+            ),
+            None => IdbRange::LowerBound(lower, false),
+        };
+        let query = IdbQuery::new()
+            .index(CREATED_AT_INDEX)
+            .range(range)
+            .reverse();
+        vec![match self.limit {
+            Some(limit) => query.limit(limit),
+            None => query,
+        }]
+    }
+    pub async fn query<B: IdbBackend>(
+        &self,
+        store: &IdbStore<StoredNote, B>,
+    ) -> IdbResult<Vec<SignedNote>> {
+        let mut seen = HashSet::new();
+        let mut notes = Vec::new();
+        for query in self.candidate_queries() {
+            for stored in store.query(query).await? {
+                if self.matches(&stored.note) && seen.insert(stored.id.clone()) {
+                    notes.push(stored.note);
+                }
+            }
+        }
+        notes.sort_by(|a, b| match supersedes(a, b) {
+            true => std::cmp::Ordering::Less,
+            false => std::cmp::Ordering::Greater,
+        });
+        if let Some(limit) = self.limit {
+            notes.truncate(limit as usize);
+        }
+        Ok(notes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nostro2::{notes::Note, userkeys::UserKeys};
+
+    use super::CacheFilter;
+
+    #[test]
+    fn filter_matches_ids_authors_kinds_tags_and_time() {
+        let keys = UserKeys::generate();
+        let mut note = Note::new(&keys.get_public_key(), 1, "reply");
+        note.created_at = 100;
+        note.tags.push(vec!["e".to_string(), "root".to_string()]);
+        let note = keys.sign_nostr_event(note);
+        let req = |filter: &str| CacheFilter::from_req(&format!(r#"["REQ","sub",{}]"#, filter));
+
+        let filter = req(&format!(
+            r##"{{"authors":["{}"],"kinds":[1],"#e":["root","other"],"since":50,"until":100}}"##,
+            keys.get_public_key()
+        ))
+        .unwrap();
+        assert_eq!(filter.tags(), vec![("e", vec!["root", "other"])]);
+        assert!(filter.matches(&note));
+        assert!(!req(r#"{"kinds":[0]}"#).unwrap().matches(&note));
+        assert!(!req(r##"{"#e":["other"]}"##).unwrap().matches(&note));
+        assert!(!req(r#"{"since":101}"#).unwrap().matches(&note));
+        assert!(req(&format!(r#"{{"ids":["{}"]}}"#, note.get_id()))
+            .unwrap()
+            .matches(&note));
+    }
+}
@@ -1,2 +1,11 @@
+pub mod buffer;
+pub mod cache_relay;
 pub mod nostr_relay;
+pub mod note_store;
+pub mod outbox;
+pub mod publish;
+pub mod relay_connection;
 pub mod relay_pool;
+pub mod replaceable;
+pub mod subscription;
+pub mod verify;
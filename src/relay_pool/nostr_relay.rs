@@ -1,6 +1,7 @@
 use wasm_bindgen::JsValue;
 
-use crate::browser_api::indexed_db::IdbStoreManager;
+use crate::browser_api::indexed_db::{backend::IdbStore, query::IdbKey, IdbStoreManager};
+use crate::nostr_db::NostrDb;
 
 #[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
 pub struct UserRelay {
@@ -9,13 +10,8 @@ pub struct UserRelay {
     pub write: bool,
 }
 impl UserRelay {
-    pub async fn get_local_relays() -> Result<Vec<Self>, JsValue>
-    where
-        Self: IdbStoreManager,
-    {
-        Self::retrieve_all_from_store::<Self>()?
-            .await
-            .map_err(|e| JsValue::from_str(&e.to_string()))
+    pub async fn get_local_relays() -> Result<Vec<Self>, JsValue> {
+        Ok(IdbStore::<Self>::default().all().await?)
     }
 }
 impl TryFrom<JsValue> for UserRelay {
@@ -31,19 +27,11 @@ impl TryInto<JsValue> for UserRelay {
     }
 }
 impl IdbStoreManager for UserRelay {
+    type Schema = NostrDb;
     fn store_name() -> &'static str {
         "user_relays"
     }
-    fn db_name() -> &'static str {
-        "nostr"
-    }
-    fn db_version() -> u32 {
-        1
-    }
-    fn document_key(&self) -> JsValue {
-        JsValue::from_str(&self.url)
-    }
-    fn upgrade_db(event: web_sys::Event) -> Result<(), JsValue> {
-        Ok(())
+    fn document_key(&self) -> IdbKey {
+        IdbKey::from(self.url.as_str())
     }
 }
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+
+use nostro2::notes::SignedNote;
+
+use crate::browser_api::indexed_db::{
+    backend::{IdbBackend, IdbStore},
+    cache::IdbCachePolicy,
+    query::{IdbKey, IdbQuery, IdbRange},
+    schema::{IdbIndexSchema, IdbStoreSchema},
+    IdbResult, IdbStoreManager,
+};
+use crate::nostr_db::NostrDb;
+
+use super::replaceable::{note_address, supersedes};
+
+pub(crate) const CREATED_AT_INDEX: &str = "created_at_id";
+pub(crate) const PUBKEY_INDEX: &str = "pubkey";
+pub(crate) const KIND_INDEX: &str = "kind";
+pub(crate) const TAG_INDEX: &str = "tags";
+pub(crate) const ADDRESS_INDEX: &str = "address";
+// Least recently read notes go first once the store outgrows this.
+const MAX_STORED_NOTES: usize = 20_000;
+
+// Single-letter tags are the only ones NIP-01 filters can select on.
+pub(super) fn tag_key(name: &str, value: &str) -> String {
+    format!("{}:{}", name, value)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct StoredNote {
+    pub id: String,
+    pub pubkey: String,
+    pub kind: u32,
+    pub created_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub note: SignedNote,
+}
+impl From<SignedNote> for StoredNote {
+    fn from(note: SignedNote) -> Self {
+        Self {
+            id: note.get_id().to_string(),
+            pubkey: note.get_pubkey().to_string(),
+            kind: note.get_kind(),
+            created_at: note.get_created_at(),
+            address: note_address(&note),
+            tags: note
+                .get_tags()
+                .iter()
+                .filter_map(|tag| match tag.as_slice() {
+                    [name, value, ..] if name.chars().count() == 1 => Some(tag_key(name, value)),
+                    _ => None,
+                })
+                .collect(),
+            note,
+        }
+    }
+}
+impl<B: IdbBackend> IdbStore<StoredNote, B> {
+    // Replaceable notes only land when they supersede every stored version of their
+    // address, and the versions they replace are deleted.
+    pub async fn archive(&self, notes: Vec<SignedNote>) -> IdbResult<()> {
+        let mut newest: HashMap<String, StoredNote> = HashMap::new();
+        let mut records = Vec::new();
+        for stored in notes.into_iter().map(StoredNote::from) {
+            match stored.address.clone() {
+                Some(address) => match newest.get(&address) {
+                    Some(current) if !supersedes(&stored.note, &current.note) => {}
+                    _ => {
+                        newest.insert(address, stored);
+                    }
+                },
+                None => records.push(stored),
+            }
+        }
+        let mut stale = Vec::new();
+        for (address, stored) in newest {
+            let existing = self.versions(&address).await?;
+            if existing
+                .iter()
+                .any(|current| current.id != stored.id && !supersedes(&stored.note, &current.note))
+            {
+                continue;
+            }
+            stale.extend(
+                existing
+                    .into_iter()
+                    .filter(|current| current.id != stored.id),
+            );
+            records.push(stored);
+        }
+        if !stale.is_empty() {
+            self.delete_many(&stale).await?;
+        }
+        self.save_many(&records).await?;
+        Ok(())
+    }
+    pub async fn latest(
+        &self,
+        pubkey: &str,
+        kind: u32,
+        d_tag: Option<&str>,
+    ) -> IdbResult<Option<SignedNote>> {
+        let address = format!("{}:{}:{}", kind, pubkey, d_tag.unwrap_or_default());
+        Ok(self
+            .versions(&address)
+            .await?
+            .into_iter()
+            .map(|stored| stored.note)
+            .reduce(|newest, note| match supersedes(&note, &newest) {
+                true => note,
+                false => newest,
+            }))
+    }
+    async fn versions(&self, address: &str) -> IdbResult<Vec<StoredNote>> {
+        let query = IdbQuery::new()
+            .index(ADDRESS_INDEX)
+            .range(IdbRange::only(address));
+        self.query(query).await
+    }
+    // Pages newest first; pass the oldest note already shown to continue from it.
+    pub async fn older_than(
+        &self,
+        before: Option<&SignedNote>,
+        limit: u32,
+    ) -> IdbResult<Vec<SignedNote>> {
+        let range = match before {
+            Some(note) => IdbRange::UpperBound(
+                IdbKey::Array(vec![
+                    IdbKey::from(note.get_created_at()),
+                    IdbKey::from(note.get_id()),
+                ]),
+                true,
+            ),
+            None => IdbRange::All,
+        };
+        let query = IdbQuery::new()
+            .index(CREATED_AT_INDEX)
+            .range(range)
+            .reverse()
+            .limit(limit);
+        Ok(self
+            .query(query)
+            .await?
+            .into_iter()
+            .map(|stored| stored.note)
+            .collect())
+    }
+}
+impl IdbStoreManager for StoredNote {
+    type Schema = NostrDb;
+    fn store_name() -> &'static str {
+        "notes"
+    }
+    fn document_key(&self) -> IdbKey {
+        IdbKey::from(self.id.as_str())
+    }
+    fn cache_policy() -> Option<IdbCachePolicy> {
+        Some(IdbCachePolicy::new().max_entries(MAX_STORED_NOTES))
+    }
+    fn store_schema() -> IdbStoreSchema {
+        IdbStoreSchema::new(Self::store_name())
+            .key_path("id")
+            .index(IdbIndexSchema::compound(
+                CREATED_AT_INDEX,
+                &["created_at", "id"],
+            ))
+            .index(IdbIndexSchema::new(ADDRESS_INDEX, "address"))
+            .index(IdbIndexSchema::new(PUBKEY_INDEX, "pubkey"))
+            .index(IdbIndexSchema::new(KIND_INDEX, "kind"))
+            .index(IdbIndexSchema::new(TAG_INDEX, "tags").multi_entry())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use nostro2::{
+        notes::{Note, SignedNote},
+        userkeys::UserKeys,
+    };
+
+    use super::StoredNote;
+    use crate::browser_api::indexed_db::{backend::IdbStore, memory::MemoryBackend};
+
+    fn signed(keys: &UserKeys, kind: u32, created_at: u64, content: &str) -> SignedNote {
+        let mut note = Note::new(&keys.get_public_key(), kind, content);
+        note.created_at = created_at;
+        keys.sign_nostr_event(note)
+    }
+
+    #[test]
+    fn archive_keeps_the_newest_replaceable_version() {
+        let store: IdbStore<StoredNote, MemoryBackend> = IdbStore::new(MemoryBackend::new());
+        let keys = UserKeys::generate();
+        let pubkey = keys.get_public_key();
+        let old_profile = signed(&keys, 0, 10, "old");
+        let profile = signed(&keys, 0, 30, "new");
+        let reply = signed(&keys, 1, 20, "reply");
+        block_on(async {
+            store
+                .archive(vec![old_profile.clone(), reply.clone()])
+                .await
+                .unwrap();
+            store.archive(vec![profile.clone()]).await.unwrap();
+            store.archive(vec![old_profile.clone()]).await.unwrap();
+            assert_eq!(
+                store.latest(&pubkey, 0, None).await.unwrap(),
+                Some(profile.clone())
+            );
+            assert_eq!(store.all().await.unwrap().len(), 2);
+            let newest = store.older_than(None, 1).await.unwrap();
+            assert_eq!(newest, vec![profile.clone()]);
+            assert_eq!(
+                store.older_than(newest.last(), 10).await.unwrap(),
+                vec![reply]
+            );
+        });
+    }
+}
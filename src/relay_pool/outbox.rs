@@ -0,0 +1,140 @@
+use std::time::Duration;
+
+use nostro2::notes::SignedNote;
+
+use crate::browser_api::indexed_db::{query::IdbKey, schema::IdbStoreSchema, IdbStoreManager};
+use crate::nostr_db::NostrDb;
+
+pub const OUTBOX_SYNC_TAG: &str = "minions-outbox";
+pub const OUTBOX_FLUSH_MESSAGE: &str = "minions-outbox-flush";
+pub const MAX_OUTBOX_ATTEMPTS: u32 = 10;
+pub const OUTBOX_RETENTION: Duration = Duration::from_secs(30);
+
+// NIP-01 OK prefixes that will not change on a resend.
+const PERMANENT_REJECTIONS: [&str; 5] = ["invalid", "blocked", "pow", "restricted", "mute"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum OutboxState {
+    Pending,
+    Sent,
+    Failed,
+}
+
+// Pending and failed entries are persisted. Sent entries leave the store as soon as a
+// relay accepts them and stay visible in the pool for `OUTBOX_RETENTION`.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct OutboxEntry {
+    pub id: String,
+    pub note: SignedNote,
+    pub state: OutboxState,
+    pub attempts: u32,
+    pub accepted_by: Vec<String>,
+    pub last_error: Option<String>,
+}
+impl OutboxEntry {
+    pub fn new(note: SignedNote) -> Self {
+        Self {
+            id: note.get_id().to_string(),
+            note,
+            state: OutboxState::Pending,
+            attempts: 0,
+            accepted_by: Vec::new(),
+            last_error: None,
+        }
+    }
+    pub fn is_pending(&self) -> bool {
+        self.state == OutboxState::Pending
+    }
+    pub fn record(&mut self, url: &str, accepted: bool, message: &str) {
+        match accepted {
+            true => {
+                self.state = OutboxState::Sent;
+                if !self.accepted_by.iter().any(|accepted| accepted == url) {
+                    self.accepted_by.push(url.to_string());
+                }
+            }
+            false => {
+                self.last_error = Some(format!("{}: {}", url, message));
+                if self.is_pending() && is_permanent_rejection(message) {
+                    self.state = OutboxState::Failed;
+                }
+            }
+        }
+    }
+    // Counts a resend; entries that used up their attempts fail instead.
+    pub fn retry(&mut self) -> bool {
+        if !self.is_pending() {
+            return false;
+        }
+        if self.attempts >= MAX_OUTBOX_ATTEMPTS {
+            self.state = OutboxState::Failed;
+            return false;
+        }
+        self.attempts += 1;
+        true
+    }
+}
+fn is_permanent_rejection(message: &str) -> bool {
+    message
+        .split_once(':')
+        .is_some_and(|(prefix, _)| PERMANENT_REJECTIONS.contains(&prefix.trim()))
+}
+
+impl IdbStoreManager for OutboxEntry {
+    type Schema = NostrDb;
+    fn store_name() -> &'static str {
+        "outbox"
+    }
+    fn document_key(&self) -> IdbKey {
+        IdbKey::from(self.id.as_str())
+    }
+    fn store_schema() -> IdbStoreSchema {
+        IdbStoreSchema::new(Self::store_name()).key_path("id")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nostro2::{notes::Note, userkeys::UserKeys};
+
+    use super::{OutboxEntry, OutboxState, MAX_OUTBOX_ATTEMPTS};
+
+    #[test]
+    fn entry_stays_pending_until_a_relay_accepts() {
+        let keys = UserKeys::generate();
+        let note = keys.sign_nostr_event(Note::new(&keys.get_public_key(), 1, "offline"));
+        let mut entry = OutboxEntry::new(note);
+        entry.record("wss://a", false, "rate-limited: slow down");
+        assert!(entry.is_pending());
+        assert_eq!(
+            entry.last_error.as_deref(),
+            Some("wss://a: rate-limited: slow down")
+        );
+        entry.record("wss://b", true, "");
+        entry.record("wss://b", true, "duplicate: already have this event");
+        assert_eq!(entry.state, OutboxState::Sent);
+        assert_eq!(entry.accepted_by, vec!["wss://b".to_string()]);
+    }
+
+    #[test]
+    fn rejected_entries_stop_retrying() {
+        let keys = UserKeys::generate();
+        let note = keys.sign_nostr_event(Note::new(&keys.get_public_key(), 1, "spam"));
+        let mut entry = OutboxEntry::new(note.clone());
+        entry.record("wss://a", false, "blocked: you are banned");
+        assert_eq!(entry.state, OutboxState::Failed);
+        assert!(!entry.retry());
+
+        let mut entry = OutboxEntry::new(note);
+        for _ in 0..MAX_OUTBOX_ATTEMPTS {
+            assert!(entry.retry());
+            entry.record("wss://a", false, "error: try again");
+        }
+        assert!(!entry.retry());
+        assert_eq!(entry.state, OutboxState::Failed);
+        assert_eq!(
+            entry.last_error.as_deref(),
+            Some("wss://a: error: try again")
+        );
+    }
+}
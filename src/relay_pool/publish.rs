@@ -0,0 +1,129 @@
+use std::{collections::HashMap, time::Duration};
+
+use yew::Callback;
+
+pub const PUBLISH_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PublishResult {
+    Accepted,
+    Rejected(String),
+    TimedOut,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct PublishReport {
+    pub note_id: String,
+    pub results: HashMap<String, PublishResult>,
+}
+impl PublishReport {
+    pub fn accepted(&self) -> usize {
+        self.results
+            .values()
+            .filter(|result| **result == PublishResult::Accepted)
+            .count()
+    }
+    pub fn total(&self) -> usize {
+        self.results.len()
+    }
+    pub fn failed_relays(&self) -> Vec<String> {
+        self.results
+            .iter()
+            .filter(|(_, result)| **result != PublishResult::Accepted)
+            .map(|(url, _)| url.clone())
+            .collect()
+    }
+}
+
+// Collects OK messages for one note until every targeted relay has answered or the
+// timeout fills in the rest. Publishing the same note again while it is pending
+// joins the existing report instead of starting another.
+pub struct PendingPublish {
+    relays: Vec<String>,
+    report: PublishReport,
+    responders: Vec<Callback<PublishReport>>,
+}
+impl PendingPublish {
+    pub fn new(note_id: String, relays: Vec<String>, responder: Callback<PublishReport>) -> Self {
+        Self {
+            relays,
+            report: PublishReport {
+                note_id,
+                results: HashMap::new(),
+            },
+            responders: vec![responder],
+        }
+    }
+    pub fn add_responder(&mut self, responder: Callback<PublishReport>) {
+        self.responders.push(responder);
+    }
+    pub fn record(&mut self, url: String, accepted: bool, message: String) {
+        if !self.relays.contains(&url) {
+            return;
+        }
+        let result = match accepted {
+            true => PublishResult::Accepted,
+            false => PublishResult::Rejected(message),
+        };
+        self.report.results.entry(url).or_insert(result);
+    }
+    pub fn is_complete(&self) -> bool {
+        self.relays
+            .iter()
+            .all(|url| self.report.results.contains_key(url))
+    }
+    pub fn time_out(&mut self) {
+        for url in self.relays.iter() {
+            self.report
+                .results
+                .entry(url.clone())
+                .or_insert(PublishResult::TimedOut);
+        }
+    }
+    pub fn respond(self) {
+        for responder in self.responders {
+            responder.emit(self.report.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use yew::Callback;
+
+    use super::{PendingPublish, PublishResult};
+
+    #[test]
+    fn pending_publish_completes_with_timeouts() {
+        let relays = vec![
+            "wss://a".to_string(),
+            "wss://b".to_string(),
+            "wss://c".to_string(),
+        ];
+        let responses = Rc::new(Cell::new(0));
+        let counter = responses.clone();
+        let responder = Callback::from(move |_| counter.set(counter.get() + 1));
+        let mut pending = PendingPublish::new("note".to_string(), relays, responder.clone());
+        pending.add_responder(responder);
+        pending.record("wss://a".to_string(), true, String::new());
+        pending.record("wss://b".to_string(), false, "blocked: spam".to_string());
+        pending.record("wss://other".to_string(), true, String::new());
+        assert!(!pending.is_complete());
+        pending.time_out();
+        assert!(pending.is_complete());
+        assert_eq!(pending.report.accepted(), 1);
+        assert_eq!(pending.report.total(), 3);
+        assert_eq!(
+            pending.report.results.get("wss://b"),
+            Some(&PublishResult::Rejected("blocked: spam".to_string()))
+        );
+        assert_eq!(
+            pending.report.results.get("wss://c"),
+            Some(&PublishResult::TimedOut)
+        );
+        pending.respond();
+        assert_eq!(responses.get(), 2);
+    }
+}
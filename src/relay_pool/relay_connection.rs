@@ -0,0 +1,273 @@
+use std::{collections::HashMap, time::Duration};
+
+use async_channel::{unbounded, Receiver, Sender};
+use futures::future::{select, Either};
+use gloo_timers::future::sleep;
+use js_sys::Date;
+use nostro2::{
+    notes::SignedNote,
+    relays::{NostrRelay, NostrSubscription, RelayEvents},
+};
+use yew::{platform::spawn_local, Callback};
+
+use super::verify::verify_note;
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Clone)]
+pub enum RelayCommand {
+    SendNote(SignedNote),
+    Subscribe(NostrSubscription),
+    Unsubscribe(String),
+    Close,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum RelayState {
+    #[default]
+    Connecting,
+    Connected,
+    Failed,
+    Closed,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RelayUpdate {
+    Event(RelayEvents),
+    Invalid(RelayEvents),
+    Connecting,
+    Connected { latency: Duration, since: f64 },
+    Failed(String),
+    Closed,
+    Sent,
+}
+
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct RelayStatus {
+    pub state: RelayState,
+    pub last_error: Option<String>,
+    pub latency: Option<Duration>,
+    pub connected_since: Option<f64>,
+    pub messages_sent: u64,
+    pub messages_received: u64,
+    pub invalid_events: u64,
+}
+impl RelayStatus {
+    pub fn is_connected(&self) -> bool {
+        self.state == RelayState::Connected
+    }
+    pub fn apply(&mut self, update: &RelayUpdate) {
+        match update {
+            RelayUpdate::Event(_) => self.messages_received += 1,
+            RelayUpdate::Invalid(_) => {
+                self.messages_received += 1;
+                self.invalid_events += 1;
+            }
+            RelayUpdate::Sent => self.messages_sent += 1,
+            RelayUpdate::Connecting => self.state = RelayState::Connecting,
+            RelayUpdate::Connected { latency, since } => {
+                self.state = RelayState::Connected;
+                self.latency = Some(*latency);
+                self.connected_since = Some(*since);
+            }
+            RelayUpdate::Failed(error) => {
+                self.state = RelayState::Failed;
+                self.last_error = Some(error.clone());
+                self.connected_since = None;
+            }
+            RelayUpdate::Closed => {
+                self.state = RelayState::Closed;
+                self.connected_since = None;
+            }
+        }
+    }
+}
+
+// Jitter picks a delay between half and the whole backoff so clients that lost the
+// same relay do not all reconnect at once.
+pub fn backoff_delay(attempt: u32, jitter: f64) -> Duration {
+    let backoff = INITIAL_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_BACKOFF);
+    backoff.mul_f64(0.5 + jitter.clamp(0.0, 1.0) / 2.0)
+}
+
+pub struct RelayConnection {
+    url: String,
+    commands: Receiver<RelayCommand>,
+    updates: Callback<(String, RelayUpdate)>,
+    subscriptions: HashMap<String, NostrSubscription>,
+}
+impl RelayConnection {
+    pub fn spawn(url: String, updates: Callback<(String, RelayUpdate)>) -> Sender<RelayCommand> {
+        let (sender, commands) = unbounded::<RelayCommand>();
+        let connection = Self {
+            url,
+            commands,
+            updates,
+            subscriptions: HashMap::new(),
+        };
+        spawn_local(connection.run());
+        sender
+    }
+
+    async fn run(mut self) {
+        let mut attempt = 0;
+        loop {
+            self.report(RelayUpdate::Connecting);
+            let started = Date::now();
+            match NostrRelay::new(&self.url).await {
+                Ok(relay) => {
+                    attempt = 0;
+                    let since = Date::now();
+                    self.report(RelayUpdate::Connected {
+                        latency: Duration::from_secs_f64((since - started).max(0.0) / 1000.0),
+                        since,
+                    });
+                    if !self.session(relay).await {
+                        self.report(RelayUpdate::Closed);
+                        return;
+                    }
+                    self.report(RelayUpdate::Failed("Connection lost".to_string()));
+                }
+                Err(e) => {
+                    gloo::console::error!("Error connecting to relay: ", &self.url, e.to_string());
+                    self.report(RelayUpdate::Failed(e.to_string()));
+                }
+            }
+            let delay = backoff_delay(attempt, js_sys::Math::random());
+            if !self.wait(delay).await {
+                self.report(RelayUpdate::Closed);
+                return;
+            }
+            attempt = attempt.saturating_add(1);
+        }
+    }
+
+    fn report(&self, update: RelayUpdate) {
+        self.updates.emit((self.url.clone(), update));
+    }
+
+    // Returns false once the pool asked the connection to close.
+    async fn session(&mut self, relay: NostrRelay) -> bool {
+        for subscription in self.subscriptions.values() {
+            match relay.subscribe(subscription).await {
+                Ok(_) => self.report(RelayUpdate::Sent),
+                Err(e) => gloo::console::error!("Error subscribing: ", format!("{:?}", e)),
+            }
+        }
+        let commands = self.commands.clone();
+        let reader = relay.relay_event_reader();
+        loop {
+            match select(Box::pin(commands.recv()), Box::pin(reader.recv())).await {
+                Either::Left((Ok(command), _)) => {
+                    if !self.execute(&relay, command).await {
+                        relay.close().await;
+                        return false;
+                    }
+                }
+                Either::Left((Err(_), _)) => {
+                    relay.close().await;
+                    return false;
+                }
+                Either::Right((Ok(event), _)) => match &event {
+                    RelayEvents::EVENT(_, note) if !verify_note(note) => {
+                        self.report(RelayUpdate::Invalid(event))
+                    }
+                    _ => self.report(RelayUpdate::Event(event)),
+                },
+                Either::Right((Err(_), _)) => {
+                    gloo::console::warn!("Relay disconnected: ", &self.url);
+                    return true;
+                }
+            }
+        }
+    }
+
+    async fn execute(&mut self, relay: &NostrRelay, command: RelayCommand) -> bool {
+        let result = match &command {
+            RelayCommand::SendNote(note) => relay.send_note(note.clone()).await,
+            RelayCommand::Subscribe(subscription) => {
+                relay.subscribe(subscription).await.map(|_| ())
+            }
+            RelayCommand::Unsubscribe(id) => relay.unsubscribe(id.clone()).await,
+            RelayCommand::Close => Ok(()),
+        };
+        match result {
+            Ok(_) if !matches!(command, RelayCommand::Close) => self.report(RelayUpdate::Sent),
+            Ok(_) => {}
+            Err(e) => gloo::console::error!("Error writing to relay: ", format!("{:?}", e)),
+        }
+        self.queue(command)
+    }
+
+    // Tracks what has to be replayed after a reconnect; returns false on close.
+    // Notes are not kept here: the pool's outbox resends them once the relay reports
+    // it is connected again.
+    fn queue(&mut self, command: RelayCommand) -> bool {
+        match command {
+            RelayCommand::SendNote(_) => {}
+            RelayCommand::Subscribe(subscription) => {
+                self.subscriptions.insert(subscription.id(), subscription);
+            }
+            RelayCommand::Unsubscribe(id) => {
+                self.subscriptions.remove(&id);
+            }
+            RelayCommand::Close => return false,
+        }
+        true
+    }
+
+    async fn wait(&mut self, delay: Duration) -> bool {
+        let commands = self.commands.clone();
+        let mut timer = Box::pin(sleep(delay));
+        loop {
+            match select(timer, Box::pin(commands.recv())).await {
+                Either::Left(_) => return true,
+                Either::Right((Ok(command), pending)) => {
+                    if !self.queue(command) {
+                        return false;
+                    }
+                    timer = pending;
+                }
+                Either::Right((Err(_), _)) => return false,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use nostro2::relays::RelayEvents;
+
+    use super::{backoff_delay, RelayState, RelayStatus, RelayUpdate};
+
+    #[test]
+    fn backoff_grows_and_caps_with_jitter() {
+        assert_eq!(backoff_delay(0, 1.0), Duration::from_secs(1));
+        assert_eq!(backoff_delay(0, 0.0), Duration::from_millis(500));
+        assert_eq!(backoff_delay(3, 1.0), Duration::from_secs(8));
+        assert_eq!(backoff_delay(40, 1.0), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn status_tracks_connection_lifecycle() {
+        let mut status = RelayStatus::default();
+        status.apply(&RelayUpdate::Connected {
+            latency: Duration::from_millis(120),
+            since: 1000.0,
+        });
+        status.apply(&RelayUpdate::Sent);
+        status.apply(&RelayUpdate::Event(RelayEvents::EOSE("sub".to_string())));
+        assert!(status.is_connected());
+        assert_eq!(status.latency, Some(Duration::from_millis(120)));
+        assert_eq!((status.messages_sent, status.messages_received), (1, 1));
+        status.apply(&RelayUpdate::Failed("Connection lost".to_string()));
+        assert_eq!(status.state, RelayState::Failed);
+        assert_eq!(status.last_error.as_deref(), Some("Connection lost"));
+        assert_eq!(status.connected_since, None);
+    }
+}
@@ -1,48 +1,134 @@
-use std::{collections::HashSet, sync::Arc};
+use std::collections::{HashMap, HashSet};
 
-use async_channel::{unbounded, Sender};
+use async_channel::Sender;
+use gloo::events::EventListener;
+use gloo_timers::future::sleep;
 use nostro2::{
     notes::SignedNote,
-    relays::{NostrRelay, NostrSubscription, RelayEvents},
+    relays::{NostrSubscription, RelayEvents},
 };
-use yew::platform::spawn_local;
-use yew::{prelude::*, props};
+use wasm_bindgen::JsCast;
+use yew::{platform::spawn_local, prelude::*, props};
+
+use crate::browser_api::indexed_db::backend::IdbStore;
+use crate::browser_api::service_worker::AppServiceWorker;
+
+use super::buffer::{
+    BufferSnapshot, RingBuffer, Shared, DEFAULT_EVENT_CAPACITY, DEFAULT_NOTE_CAPACITY,
+};
+use super::cache_relay::{CacheFilter, CACHE_RELAY_URL};
+use super::nostr_relay::UserRelay;
+use super::note_store::StoredNote;
+use super::outbox::{
+    OutboxEntry, OutboxState, OUTBOX_FLUSH_MESSAGE, OUTBOX_RETENTION, OUTBOX_SYNC_TAG,
+};
+use super::publish::{PendingPublish, PublishReport, PUBLISH_TIMEOUT};
+use super::relay_connection::{RelayCommand, RelayConnection, RelayStatus, RelayUpdate};
+use super::replaceable::{ReplaceableIndex, ReplaceableOffer};
+use super::subscription::SubscriptionFeed;
+use super::verify::NoteVerification;
+
+const CACHE_FLUSH_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
 
 pub enum RelayAction {
-    Event(RelayEvents),
-    SendNote(SignedNote),
-    Subscribe(NostrSubscription),
+    Relay(String, RelayUpdate),
+    SendNote(SignedNote, RelayTarget),
+    Publish(SignedNote, Callback<PublishReport>),
+    PublishTimeout(String),
+    Subscribe(NostrSubscription, RelayTarget),
     Unsubscribe(String),
+    AddRelay(UserRelay),
+    RemoveRelay(String),
+    OutboxLoaded(Vec<OutboxEntry>),
+    FlushOutbox,
+    DismissOutbox(String),
+    CachedNotes(String, Vec<SignedNote>),
+    FlushCache,
     Close,
 }
 
+// Read relays receive subscriptions and write relays receive notes unless the call
+// names the relays it wants.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub enum RelayTarget {
+    #[default]
+    Preferred,
+    Urls(Vec<String>),
+}
+impl RelayTarget {
+    fn accepts(&self, relay: &UserRelay, preferred: bool) -> bool {
+        match self {
+            Self::Preferred => preferred,
+            Self::Urls(urls) => urls.contains(&relay.url),
+        }
+    }
+}
+
 #[derive(Properties, Clone, PartialEq)]
 pub struct NostrProps {
-    pub relay_events: Vec<RelayEvents>,
-    pub notes: Vec<SignedNote>,
+    pub relay_events: BufferSnapshot<RelayEvents>,
+    pub notes: BufferSnapshot<SignedNote>,
+    pub relay_status: Shared<HashMap<String, RelayStatus>>,
+    pub subscription_feeds: Shared<HashMap<String, Shared<SubscriptionFeed>>>,
+    pub flagged_notes: Shared<HashSet<String>>,
+    pub replaceable: ReplaceableIndex,
+    pub outbox: Shared<HashMap<String, OutboxEntry>>,
+    pub flush_outbox: Callback<()>,
+    pub dismiss_outbox: Callback<String>,
     pub send_note: Callback<SignedNote>,
+    pub publish: Callback<(SignedNote, Callback<PublishReport>)>,
     pub subscribe: Callback<NostrSubscription>,
+    pub send_note_to: Callback<(SignedNote, Vec<String>)>,
+    pub subscribe_to: Callback<(NostrSubscription, Vec<String>)>,
     pub unsubscribe: Callback<String>,
+    pub add_relay: Callback<UserRelay>,
+    pub remove_relay: Callback<String>,
     pub close: Callback<()>,
 }
 
 #[derive(Clone, Debug, Properties, PartialEq)]
 pub struct RelayContextProps {
     pub children: Children,
-    pub user_relays: Vec<super::nostr_relay::UserRelay>,
+    pub user_relays: Vec<UserRelay>,
+    #[prop_or(DEFAULT_EVENT_CAPACITY)]
+    pub event_capacity: usize,
+    #[prop_or(DEFAULT_NOTE_CAPACITY)]
+    pub note_capacity: usize,
+    #[prop_or_default]
+    pub verification: NoteVerification,
+    #[prop_or_default]
+    pub cache_relay: bool,
 }
 
 pub struct RelayPool {
-    relay_events: Vec<RelayEvents>,
-    new_notes: Vec<SignedNote>,
+    relay_events: RingBuffer<RelayEvents>,
+    new_notes: RingBuffer<SignedNote>,
     unique_ids: HashSet<String>,
-    sender_channel: Vec<Sender<SignedNote>>,
-    filter_channel: Vec<Sender<NostrSubscription>>,
-    unsubscribe_channel: Vec<Sender<String>>,
-    close_channel: Vec<Sender<()>>,
+    verification: NoteVerification,
+    flagged_notes: Shared<HashSet<String>>,
+    replaceable: ReplaceableIndex,
+    outbox: Shared<HashMap<String, OutboxEntry>>,
+    flush_outbox_callback: Callback<()>,
+    dismiss_outbox_callback: Callback<String>,
+    _outbox_listener: Option<EventListener>,
+    cache_relay: bool,
+    pending_cache: Vec<SignedNote>,
+    cached_notes_callback: Callback<(String, Vec<SignedNote>)>,
+    flush_cache_callback: Callback<()>,
+    relay_status: Shared<HashMap<String, RelayStatus>>,
+    relay_channels: Vec<(UserRelay, Sender<RelayCommand>)>,
+    subscriptions: Vec<(NostrSubscription, RelayTarget)>,
+    subscription_feeds: Shared<HashMap<String, Shared<SubscriptionFeed>>>,
+    pending_publishes: HashMap<String, PendingPublish>,
+    relay_update_callback: Callback<(String, RelayUpdate)>,
     send_note_callback: Callback<SignedNote>,
+    publish_callback: Callback<(SignedNote, Callback<PublishReport>)>,
     subscribe_callback: Callback<NostrSubscription>,
+    send_note_to_callback: Callback<(SignedNote, Vec<String>)>,
+    subscribe_to_callback: Callback<(NostrSubscription, Vec<String>)>,
     unsubscribe_callback: Callback<String>,
+    add_relay_callback: Callback<UserRelay>,
+    remove_relay_callback: Callback<String>,
     close_callback: Callback<()>,
     children: Children,
 }
@@ -61,61 +147,212 @@ impl Component for RelayPool {
         }
     }
     fn create(ctx: &Context<Self>) -> Self {
-        let relays = ctx.props().user_relays.clone();
-        let (sender_channel, filter_channel, unsubscribe_channel, close_channel) =
-            Self::read_relay(ctx.link().callback(RelayAction::Event), relays);
-        let send_note_callback = ctx.link().callback(RelayAction::SendNote);
+        let relay_update_callback = ctx
+            .link()
+            .callback(|(url, update)| RelayAction::Relay(url, update));
+        let send_note_callback = ctx
+            .link()
+            .callback(|note| RelayAction::SendNote(note, RelayTarget::Preferred));
+        let publish_callback = ctx
+            .link()
+            .callback(|(note, responder)| RelayAction::Publish(note, responder));
         let close_callback = ctx.link().callback(move |_| RelayAction::Close);
-        let subscribe_callback = ctx.link().callback(RelayAction::Subscribe);
+        let subscribe_callback = ctx
+            .link()
+            .callback(|filter| RelayAction::Subscribe(filter, RelayTarget::Preferred));
+        let send_note_to_callback = ctx
+            .link()
+            .callback(|(note, urls)| RelayAction::SendNote(note, RelayTarget::Urls(urls)));
+        let subscribe_to_callback = ctx
+            .link()
+            .callback(|(filter, urls)| RelayAction::Subscribe(filter, RelayTarget::Urls(urls)));
         let unsubscribe_callback = ctx.link().callback(RelayAction::Unsubscribe);
+        let add_relay_callback = ctx.link().callback(RelayAction::AddRelay);
+        let remove_relay_callback = ctx.link().callback(RelayAction::RemoveRelay);
         let children = ctx.props().children.clone();
-        let relay_events = Vec::new();
-        let new_notes = Vec::new();
+        let relay_events = RingBuffer::new(ctx.props().event_capacity);
+        let new_notes = RingBuffer::new(ctx.props().note_capacity);
         let unique_ids = HashSet::new();
 
-        Self {
+        let mut pool = Self {
             relay_events,
             new_notes,
             unique_ids,
-            sender_channel,
-            close_channel,
-            filter_channel,
-            unsubscribe_channel,
+            verification: ctx.props().verification,
+            flagged_notes: Shared::default(),
+            replaceable: ReplaceableIndex::default(),
+            outbox: Shared::default(),
+            flush_outbox_callback: ctx.link().callback(|_| RelayAction::FlushOutbox),
+            dismiss_outbox_callback: ctx.link().callback(RelayAction::DismissOutbox),
+            _outbox_listener: Self::listen_for_sync(ctx),
+            cache_relay: ctx.props().cache_relay,
+            pending_cache: Vec::new(),
+            cached_notes_callback: ctx
+                .link()
+                .callback(|(id, notes)| RelayAction::CachedNotes(id, notes)),
+            flush_cache_callback: ctx.link().callback(|_| RelayAction::FlushCache),
+            relay_status: Shared::default(),
+            relay_channels: Vec::new(),
+            subscriptions: Vec::new(),
+            subscription_feeds: Shared::default(),
+            pending_publishes: HashMap::new(),
+            relay_update_callback,
             send_note_callback,
+            publish_callback,
             close_callback,
             subscribe_callback,
+            send_note_to_callback,
+            subscribe_to_callback,
             unsubscribe_callback,
+            add_relay_callback,
+            remove_relay_callback,
             children,
+        };
+        for relay in ctx.props().user_relays.iter() {
+            pool.add_relay(relay.clone());
+        }
+        ctx.link().send_future(async {
+            match IdbStore::<OutboxEntry>::default().all().await {
+                Ok(entries) => RelayAction::OutboxLoaded(entries),
+                Err(e) => {
+                    gloo::console::error!("Error loading outbox: ", e.to_string());
+                    RelayAction::OutboxLoaded(Vec::new())
+                }
+            }
+        });
+        pool
+    }
+    fn changed(&mut self, ctx: &Context<Self>, old_props: &Self::Properties) -> bool {
+        let new_relays = &ctx.props().user_relays;
+        for relay in old_props.user_relays.iter() {
+            if !new_relays.iter().any(|new| new.url == relay.url) {
+                self.remove_relay(&relay.url);
+            }
         }
+        for relay in new_relays.iter() {
+            if !old_props.user_relays.contains(relay) {
+                self.add_relay(relay.clone());
+            }
+        }
+        self.verification = ctx.props().verification;
+        self.cache_relay = ctx.props().cache_relay;
+        self.relay_events.set_capacity(ctx.props().event_capacity);
+        let evicted = self.new_notes.set_capacity(ctx.props().note_capacity);
+        self.archive_notes(evicted);
+        self.children = ctx.props().children.clone();
+        true
     }
-    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
-            RelayAction::SendNote(note) => {
-                self.send_nostr_note(note);
+            RelayAction::SendNote(note, target) => {
+                self.queue_outbox(note.clone());
+                self.send_nostr_note(note, target);
+                true
+            }
+            RelayAction::OutboxLoaded(entries) => {
+                for entry in entries {
+                    if self.outbox.contains_key(&entry.id) {
+                        continue;
+                    }
+                    self.outbox.make_mut().insert(entry.id.clone(), entry);
+                }
+                self.flush_outbox();
+                true
+            }
+            RelayAction::FlushOutbox => {
+                self.flush_outbox();
+                true
+            }
+            RelayAction::DismissOutbox(id) => self.dismiss_outbox(&id),
+            RelayAction::CachedNotes(id, notes) => {
+                for note in notes {
+                    self.ingest(CACHE_RELAY_URL, RelayEvents::EVENT(id.clone(), note), true);
+                }
+                self.ingest(CACHE_RELAY_URL, RelayEvents::EOSE(id), true);
                 true
             }
-            RelayAction::Subscribe(filter) => {
-                self.subscribe(filter);
+            RelayAction::FlushCache => {
+                let notes = std::mem::take(&mut self.pending_cache);
+                spawn_local(async move {
+                    if let Err(e) = IdbStore::<StoredNote>::default().archive(notes).await {
+                        gloo::console::error!("Error caching notes: ", e.to_string());
+                    }
+                });
+                false
+            }
+            RelayAction::Publish(note, responder) => {
+                let note_id = note.get_id().to_string();
+                if let Some(pending) = self.pending_publishes.get_mut(&note_id) {
+                    pending.add_responder(responder);
+                    return true;
+                }
+                let relays = self
+                    .relay_channels
+                    .iter()
+                    .filter(|(relay, _)| relay.write)
+                    .map(|(relay, _)| relay.url.clone())
+                    .collect();
+                let pending = PendingPublish::new(note_id.clone(), relays, responder);
+                if pending.is_complete() {
+                    pending.respond();
+                    return false;
+                }
+                self.pending_publishes.insert(note_id.clone(), pending);
+                self.queue_outbox(note.clone());
+                self.send_nostr_note(note, RelayTarget::Preferred);
+                ctx.link().send_future(async move {
+                    sleep(PUBLISH_TIMEOUT).await;
+                    RelayAction::PublishTimeout(note_id)
+                });
+                true
+            }
+            RelayAction::PublishTimeout(note_id) => {
+                if let Some(mut pending) = self.pending_publishes.remove(&note_id) {
+                    pending.time_out();
+                    pending.respond();
+                }
+                false
+            }
+            RelayAction::Subscribe(filter, target) => {
+                self.subscribe(filter, target);
                 true
             }
             RelayAction::Close => {
                 self.close_ws();
                 true
             }
-            RelayAction::Event(event) => {
-                if let RelayEvents::EVENT(_, ref note) = event {
-                    if !self.unique_ids.contains(note.get_id()) {
-                        self.unique_ids.insert(note.get_id().to_string());
-                        self.new_notes.push(note.clone());
+            RelayAction::Relay(url, update) => {
+                // Late updates from a removed relay should not bring its status back.
+                if self.relay_status.contains_key(&url) {
+                    if let Some(status) = self.relay_status.make_mut().get_mut(&url) {
+                        status.apply(&update);
+                    }
+                }
+                match update {
+                    RelayUpdate::Event(event) => self.ingest(&url, event, true),
+                    RelayUpdate::Connected { .. } => self.flush_outbox_to_relay(&url),
+                    RelayUpdate::Invalid(event) => {
+                        gloo::console::warn!("Invalid event from relay: ", &url);
+                        if self.verification == NoteVerification::Flag {
+                            self.ingest(&url, event, false);
+                        }
                     }
+                    _ => {}
                 }
-                self.add_event(event);
                 true
             }
             RelayAction::Unsubscribe(filter) => {
                 self.unsubscribe(filter);
                 true
             }
+            RelayAction::AddRelay(relay) => {
+                self.add_relay(relay);
+                true
+            }
+            RelayAction::RemoveRelay(url) => {
+                self.remove_relay(&url);
+                true
+            }
         }
     }
     fn destroy(&mut self, _ctx: &Context<Self>) {
@@ -124,133 +361,634 @@ impl Component for RelayPool {
 }
 
 impl RelayPool {
-    fn read_relay(
-        note_cb: Callback<RelayEvents>,
-        relays: Vec<super::nostr_relay::UserRelay>,
-    ) -> (
-        Vec<Sender<SignedNote>>,
-        Vec<Sender<NostrSubscription>>,
-        Vec<Sender<String>>,
-        Vec<Sender<()>>,
-    ) {
-        let mut send_note_channels = vec![];
-        let mut filter_channels = vec![];
-        let mut unsubscribe_channels = vec![];
-        let mut close_channels = vec![];
-
-        for relay in relays {
-            let (send_note_tx, send_note_rx) = unbounded::<SignedNote>();
-            let (filter_tx, filter_rx) = unbounded::<NostrSubscription>();
-            let (unsubscribe_tx, unsubscribe_rx) = unbounded::<String>();
-            let (close_tx, close_rx) = unbounded::<()>();
-            send_note_channels.push(send_note_tx);
-            filter_channels.push(filter_tx);
-            unsubscribe_channels.push(unsubscribe_tx);
-            close_channels.push(close_tx);
-            let note_cb = note_cb.clone();
-            spawn_local(async move {
-                let relay = NostrRelay::new(&relay.url).await;
-                if let Err(_) = relay {
-                    return;
-                };
-                let relay = relay.unwrap();
-                let relay_arc = Arc::new(relay);
+    pub fn build_props(&self) -> NostrProps {
+        props!(NostrProps {
+            relay_events: self.relay_events.snapshot(),
+            notes: self.new_notes.snapshot(),
+            relay_status: self.relay_status.clone(),
+            subscription_feeds: self.subscription_feeds.clone(),
+            flagged_notes: self.flagged_notes.clone(),
+            replaceable: self.replaceable.clone(),
+            outbox: self.outbox.clone(),
+            flush_outbox: self.flush_outbox_callback.clone(),
+            dismiss_outbox: self.dismiss_outbox_callback.clone(),
+            send_note: self.send_note_callback.clone(),
+            publish: self.publish_callback.clone(),
+            subscribe: self.subscribe_callback.clone(),
+            send_note_to: self.send_note_to_callback.clone(),
+            subscribe_to: self.subscribe_to_callback.clone(),
+            unsubscribe: self.unsubscribe_callback.clone(),
+            add_relay: self.add_relay_callback.clone(),
+            remove_relay: self.remove_relay_callback.clone(),
+            close: self.close_callback.clone(),
+        })
+    }
 
-                let sender_relay = relay_arc.clone();
-                spawn_local(async move {
-                    while let Ok(note) = send_note_rx.recv().await {
-                        if let Err(e) = sender_relay.send_note(note).await {
-                            gloo::console::error!("Error sending note: ", format!("{:?}", e));
-                        }
-                    }
-                });
+    fn send_to_relays(&self, command: RelayCommand, accepts: impl Fn(&UserRelay) -> bool) {
+        self.relay_channels
+            .iter()
+            .filter(|(relay, _)| accepts(relay))
+            .for_each(|(_, channel)| send_command(channel, command.clone()));
+    }
 
-                let filter_relay = relay_arc.clone();
-                spawn_local(async move {
-                    while let Ok(filter) = filter_rx.recv().await {
-                        if let Err(e) = filter_relay.subscribe(&filter).await {
-                            gloo::console::error!("Error subscribing: ", format!("{:?}", e));
-                        }
-                    }
-                });
+    fn warn_unknown_urls(&self, target: &RelayTarget) {
+        if let RelayTarget::Urls(urls) = target {
+            urls.iter()
+                .filter(|url| {
+                    !self
+                        .relay_channels
+                        .iter()
+                        .any(|(relay, _)| &relay.url == *url)
+                })
+                .for_each(|url| gloo::console::warn!("Relay not in pool: ", url));
+        }
+    }
 
-                let unsubscribe_relay = relay_arc.clone();
-                spawn_local(async move {
-                    while let Ok(filter) = unsubscribe_rx.recv().await {
-                        if let Err(e) = unsubscribe_relay.unsubscribe(filter).await {
-                            gloo::console::error!("Error unsubscribing: ", format!("{:?}", e));
-                        }
-                    }
-                });
+    // Relays that are down get the note from the outbox once they report connected,
+    // so every note reaches a relay through a single resend path.
+    fn send_nostr_note(&self, signed_note: SignedNote, target: RelayTarget) {
+        self.warn_unknown_urls(&target);
+        self.send_to_relays(RelayCommand::SendNote(signed_note), |relay| {
+            self.writes_to(relay, &target)
+        });
+    }
 
-                let reader_relay = relay_arc.clone();
-                spawn_local(async move {
-                    while let Ok(event) = reader_relay.relay_event_reader().recv().await {
-                        note_cb.emit(event);
+    fn writes_to(&self, relay: &UserRelay, target: &RelayTarget) -> bool {
+        target.accepts(relay, relay.write)
+            && self
+                .relay_status
+                .get(&relay.url)
+                .is_some_and(RelayStatus::is_connected)
+    }
+
+    fn subscribe(&mut self, filter: NostrSubscription, target: RelayTarget) {
+        self.warn_unknown_urls(&target);
+        self.send_to_relays(RelayCommand::Subscribe(filter.clone()), |relay| {
+            target.accepts(relay, relay.read)
+        });
+        let mut relays: Vec<String> = self
+            .relay_channels
+            .iter()
+            .filter(|(relay, _)| target.accepts(relay, relay.read))
+            .map(|(relay, _)| relay.url.clone())
+            .collect();
+        if self.cache_relay {
+            if let Some(cache_filter) = CacheFilter::from_subscription(&filter) {
+                relays.push(CACHE_RELAY_URL.to_string());
+                self.query_cache(filter.id(), cache_filter);
+            }
+        }
+        self.subscription_feeds.make_mut().insert(
+            filter.id(),
+            Shared::new(SubscriptionFeed::new(relays, self.new_notes.capacity())),
+        );
+        self.subscriptions
+            .retain(|(subscription, _)| subscription.id() != filter.id());
+        self.subscriptions.push((filter, target));
+    }
+
+    // Every relay gets the close so subscriptions opened with explicit targets end too.
+    fn unsubscribe(&mut self, filter: String) {
+        self.send_to_relays(RelayCommand::Unsubscribe(filter.clone()), |_| true);
+        self.subscriptions
+            .retain(|(subscription, _)| subscription.id() != filter);
+        self.subscription_feeds.make_mut().remove(&filter);
+    }
+
+    // Re-adding a known url swaps its read/write flags and opens or closes the pool's
+    // subscriptions on it to match; new relays pick up every subscription they read.
+    fn add_relay(&mut self, relay: UserRelay) {
+        let existing = self
+            .relay_channels
+            .iter()
+            .position(|(existing, _)| existing.url == relay.url);
+        if let Some(position) = existing {
+            let (previous, channel) = &self.relay_channels[position];
+            let mut opened = Vec::new();
+            let mut closed = Vec::new();
+            for (subscription, target) in self.subscriptions.iter() {
+                let was_reading = target.accepts(previous, previous.read);
+                let reading = target.accepts(&relay, relay.read);
+                match (was_reading, reading) {
+                    (false, true) => {
+                        send_command(channel, RelayCommand::Subscribe(subscription.clone()));
+                        opened.push(subscription.id());
                     }
-                });
+                    (true, false) => {
+                        send_command(channel, RelayCommand::Unsubscribe(subscription.id()));
+                        closed.push(subscription.id());
+                    }
+                    _ => {}
+                }
+            }
+            for id in opened {
+                if let Some(feed) = self.feed_mut(&id) {
+                    feed.add_relay(relay.url.clone());
+                }
+            }
+            for id in closed {
+                if let Some(feed) = self.feed_mut(&id) {
+                    feed.remove_relay(&relay.url);
+                }
+            }
+            self.relay_channels[position].0 = relay;
+            return;
+        }
+        let channel = RelayConnection::spawn(relay.url.clone(), self.relay_update_callback.clone());
+        let reading: Vec<String> = self
+            .subscriptions
+            .iter()
+            .filter(|(_, target)| target.accepts(&relay, relay.read))
+            .map(|(subscription, _)| {
+                send_command(&channel, RelayCommand::Subscribe(subscription.clone()));
+                subscription.id()
+            })
+            .collect();
+        for id in reading {
+            if let Some(feed) = self.feed_mut(&id) {
+                feed.add_relay(relay.url.clone());
+            }
+        }
+        self.relay_status
+            .make_mut()
+            .insert(relay.url.clone(), RelayStatus::default());
+        self.relay_channels.push((relay, channel));
+    }
 
-                let close_relay = relay_arc.clone();
-                spawn_local(async move {
-                    while let Ok(_) = close_rx.recv().await {
-                        close_relay.close().await;
+    fn remove_relay(&mut self, url: &str) {
+        self.relay_channels.retain(|(relay, channel)| {
+            if relay.url != url {
+                return true;
+            }
+            if let Err(e) = channel.try_send(RelayCommand::Close) {
+                gloo::console::error!("Error closing relay: ", format!("{:?}", e));
+            }
+            false
+        });
+        self.relay_status.make_mut().remove(url);
+        self.update_feeds(|feed| feed.remove_relay(url));
+    }
+
+    fn record_publish(&mut self, url: &str, note_id: &str, accepted: bool, message: &str) {
+        let Some(pending) = self.pending_publishes.get_mut(note_id) else {
+            return;
+        };
+        pending.record(url.to_string(), accepted, message.to_string());
+        if pending.is_complete() {
+            if let Some(pending) = self.pending_publishes.remove(note_id) {
+                pending.respond();
+            }
+        }
+    }
+
+    // Notes leaving memory go to the note store so consumers can page back to them.
+    fn archive_notes(&mut self, evicted: Vec<SignedNote>) {
+        let evicted = self.release_notes(evicted);
+        // With the cache relay on, notes were already stored when they arrived.
+        if evicted.is_empty() || self.cache_relay {
+            return;
+        }
+        spawn_local(async move {
+            if let Err(e) = IdbStore::<StoredNote>::default().archive(evicted).await {
+                gloo::console::error!("Error archiving notes: ", e.to_string());
+            }
+        });
+    }
+
+    // The store keeps no flags, so flagged notes are forgotten instead of archived
+    // where a later page would hand them back as genuine.
+    fn release_notes(&mut self, evicted: Vec<SignedNote>) -> Vec<SignedNote> {
+        evicted
+            .into_iter()
+            .filter(|note| {
+                self.unique_ids.remove(note.get_id());
+                self.replaceable.forget(note);
+                let flagged = self.flagged_notes.contains(note.get_id());
+                self.unflag(note.get_id());
+                !flagged
+            })
+            .collect()
+    }
+
+    fn ingest(&mut self, url: &str, event: RelayEvents, verified: bool) {
+        if let RelayEvents::OK(ref id, accepted, ref message) = event {
+            self.record_publish(url, id, accepted, message);
+            self.record_outbox(url, id, accepted, message);
+        }
+        if let RelayEvents::EOSE(ref id) = event {
+            if let Some(feed) = self.feed_mut(id) {
+                feed.mark_eose(url);
+            }
+        }
+        if let RelayEvents::EVENT(ref id, ref note) = event {
+            let held = self.unique_ids.contains(note.get_id());
+            let flagged = self.flagged_notes.contains(note.get_id());
+            match verified {
+                // Flags follow the copy that is held, so a relay resending a genuine
+                // note's id with a bad signature cannot taint it.
+                false if held && !flagged => return,
+                false => {
+                    self.flagged_notes
+                        .make_mut()
+                        .insert(note.get_id().to_string());
+                }
+                true if flagged => self.drop_note(note.get_id()),
+                true => {}
+            }
+            // Only verified notes take part in replacement, so a forged newer version
+            // cannot evict the genuine one.
+            match verified.then(|| self.replaceable.offer(note)) {
+                Some(ReplaceableOffer::Stale) => {
+                    self.add_event(event);
+                    return;
+                }
+                Some(ReplaceableOffer::Current(Some(previous))) => {
+                    // Every feed that showed the old version shows the new one instead.
+                    let holders: Vec<String> = self
+                        .subscription_feeds
+                        .iter()
+                        .filter(|(_, feed)| feed.contains(previous.get_id()))
+                        .map(|(id, _)| id.clone())
+                        .collect();
+                    self.drop_note(previous.get_id());
+                    for holder in holders {
+                        if let Some(feed) = self.feed_mut(&holder) {
+                            feed.push(note.clone());
+                        }
                     }
-                });
-            });
+                }
+                _ => {}
+            }
+            if let Some(feed) = self.feed_mut(id) {
+                feed.push(note.clone());
+            }
+            if !self.unique_ids.contains(note.get_id()) {
+                self.unique_ids.insert(note.get_id().to_string());
+                if url != CACHE_RELAY_URL && verified {
+                    self.queue_cache(note.clone());
+                }
+                let evicted = self.new_notes.push(note.clone());
+                self.archive_notes(evicted);
+            }
         }
+        self.add_event(event);
+    }
 
-        (
-            send_note_channels,
-            filter_channels,
-            unsubscribe_channels,
-            close_channels,
-        )
+    fn listen_for_sync(ctx: &Context<Self>) -> Option<EventListener> {
+        let container = web_sys::window()?.navigator().service_worker();
+        let link = ctx.link().clone();
+        Some(EventListener::new(&container, "message", move |event| {
+            let Some(event) = event.dyn_ref::<web_sys::MessageEvent>() else {
+                return;
+            };
+            let kind = js_sys::Reflect::get(&event.data(), &"type".into()).ok();
+            if kind.and_then(|kind| kind.as_string()).as_deref() == Some(OUTBOX_FLUSH_MESSAGE) {
+                link.send_message(RelayAction::FlushOutbox);
+            }
+        }))
     }
 
-    pub fn build_props(&self) -> NostrProps {
-        props!(NostrProps {
-            relay_events: self.relay_events.clone(),
-            notes: self.new_notes.clone(),
-            send_note: self.send_note_callback.clone(),
-            subscribe: self.subscribe_callback.clone(),
-            unsubscribe: self.unsubscribe_callback.clone(),
-            close: self.close_callback.clone(),
-        })
+    // Notes are persisted before they hit the relay channels so a reload or a dead
+    // connection cannot lose them; offline sends also ask the service worker to
+    // wake the app once connectivity returns. A failed note sent again starts over.
+    fn queue_outbox(&mut self, note: SignedNote) {
+        let queued = self.outbox.get(note.get_id());
+        if queued.is_some_and(|entry| entry.state != OutboxState::Failed) {
+            return;
+        }
+        let entry = OutboxEntry::new(note);
+        self.outbox
+            .make_mut()
+            .insert(entry.id.clone(), entry.clone());
+        let offline = web_sys::window().is_some_and(|window| !window.navigator().on_line());
+        spawn_local(async move {
+            if let Err(e) = IdbStore::default().save(&entry).await {
+                gloo::console::error!("Error saving note to outbox: ", e.to_string());
+            }
+            if offline {
+                let registered = async {
+                    AppServiceWorker::new()?
+                        .register_sync(OUTBOX_SYNC_TAG)
+                        .await
+                };
+                if let Err(e) = registered.await {
+                    gloo::console::warn!("Background sync unavailable: ", e);
+                }
+            }
+        });
     }
 
-    fn send_nostr_note(&self, signed_note: SignedNote) {
-        self.sender_channel.iter().for_each(|channel| {
-            if let Err(e) = channel.try_send(signed_note.clone()) {
-                gloo::console::error!("Error sending note: ", format!("{:?}", e));
+    fn record_outbox(&mut self, url: &str, note_id: &str, accepted: bool, message: &str) {
+        if !self.outbox.contains_key(note_id) {
+            return;
+        }
+        let Some(entry) = self.outbox.make_mut().get_mut(note_id) else {
+            return;
+        };
+        let state = entry.state;
+        entry.record(url, accepted, message);
+        let entry = entry.clone();
+        if entry.state == OutboxState::Sent && state != OutboxState::Sent {
+            let dismiss = self.dismiss_outbox_callback.clone();
+            let id = entry.id.clone();
+            spawn_local(async move {
+                sleep(OUTBOX_RETENTION).await;
+                dismiss.emit(id);
+            });
+        }
+        spawn_local(async move {
+            let persisted = async {
+                let store = IdbStore::default();
+                match entry.state {
+                    OutboxState::Sent => store.delete(&entry).await,
+                    _ => store.save(&entry).await,
+                }
+            };
+            if let Err(e) = persisted.await {
+                gloo::console::error!("Error updating outbox: ", e.to_string());
             }
         });
     }
 
-    fn subscribe(&self, filter: NostrSubscription) {
-        self.filter_channel.iter().for_each(|channel| {
-            if let Err(e) = channel.try_send(filter.clone()) {
-                gloo::console::error!("Error sending filter: ", format!("{:?}", e));
+    // Finished entries leave the outbox; pending ones keep retrying until they finish.
+    fn dismiss_outbox(&mut self, note_id: &str) -> bool {
+        if self.outbox.get(note_id).is_none_or(OutboxEntry::is_pending) {
+            return false;
+        }
+        let Some(entry) = self.outbox.make_mut().remove(note_id) else {
+            return false;
+        };
+        spawn_local(async move {
+            if let Err(e) = IdbStore::default().delete(&entry).await {
+                gloo::console::error!("Error updating outbox: ", e.to_string());
             }
         });
+        true
+    }
+
+    fn flush_outbox(&mut self) {
+        self.flush_outbox_to(RelayTarget::Preferred);
     }
 
-    fn unsubscribe(&self, filter: String) {
-        self.unsubscribe_channel.iter().for_each(|channel| {
-            if let Err(e) = channel.try_send(filter.clone()) {
-                gloo::console::error!("Error sending unsubscribe: ", format!("{:?}", e));
+    // A relay that just connected gets every pending note, whether it was down,
+    // offline or added after the note was queued.
+    fn flush_outbox_to_relay(&mut self, url: &str) {
+        self.flush_outbox_to(RelayTarget::Urls(vec![url.to_string()]));
+    }
+
+    // Only a flush that reaches a connected relay counts as an attempt.
+    fn flush_outbox_to(&mut self, target: RelayTarget) {
+        let reachable = self
+            .relay_channels
+            .iter()
+            .any(|(relay, _)| self.writes_to(relay, &target));
+        if !reachable || !self.outbox.values().any(OutboxEntry::is_pending) {
+            return;
+        }
+        let mut retried = Vec::new();
+        let mut notes = Vec::new();
+        for entry in self.outbox.make_mut().values_mut() {
+            if !entry.is_pending() {
+                continue;
+            }
+            if entry.retry() {
+                notes.push(entry.note.clone());
+            }
+            retried.push(entry.clone());
+        }
+        for note in notes {
+            self.send_nostr_note(note, target.clone());
+        }
+        spawn_local(async move {
+            if let Err(e) = IdbStore::default().save_many(&retried).await {
+                gloo::console::error!("Error updating outbox: ", e.to_string());
             }
         });
     }
 
+    // Local results land first and count as one more relay towards the feed's EOSE.
+    fn query_cache(&self, id: String, filter: CacheFilter) {
+        let callback = self.cached_notes_callback.clone();
+        spawn_local(async move {
+            let notes = filter
+                .query(&IdbStore::default())
+                .await
+                .unwrap_or_else(|e| {
+                    gloo::console::error!("Error reading cache relay: ", e.to_string());
+                    Vec::new()
+                });
+            callback.emit((id, notes));
+        });
+    }
+
+    // Writes are batched so a burst of relay events becomes a single transaction.
+    fn queue_cache(&mut self, note: SignedNote) {
+        if !self.cache_relay {
+            return;
+        }
+        if self.pending_cache.is_empty() {
+            let callback = self.flush_cache_callback.clone();
+            spawn_local(async move {
+                sleep(CACHE_FLUSH_DELAY).await;
+                callback.emit(());
+            });
+        }
+        self.pending_cache.push(note);
+    }
+
+    fn drop_note(&mut self, note_id: &str) {
+        self.new_notes.retain(|note| note.get_id() != note_id);
+        self.unique_ids.remove(note_id);
+        self.unflag(note_id);
+        self.update_feeds(|feed| feed.remove(note_id));
+    }
+
+    fn unflag(&mut self, note_id: &str) {
+        if self.flagged_notes.contains(note_id) {
+            self.flagged_notes.make_mut().remove(note_id);
+        }
+    }
+
+    fn feed_mut(&mut self, id: &str) -> Option<&mut SubscriptionFeed> {
+        if !self.subscription_feeds.contains_key(id) {
+            return None;
+        }
+        self.subscription_feeds
+            .make_mut()
+            .get_mut(id)
+            .map(Shared::make_mut)
+    }
+
+    fn update_feeds(&mut self, update: impl Fn(&mut SubscriptionFeed)) {
+        self.subscription_feeds
+            .make_mut()
+            .values_mut()
+            .for_each(|feed| update(feed.make_mut()));
+    }
+
     fn add_event(&mut self, event: RelayEvents) {
         self.relay_events.push(event);
     }
 
     fn close_ws(&self) {
-        self.close_channel.iter().for_each(|channel| {
-            if let Err(e) = channel.try_send(()) {
-                gloo::console::error!("Error closing WS", format!("{:?}", e));
-            }
-        });
+        self.send_to_relays(RelayCommand::Close, |_| true);
+    }
+}
+
+fn send_command(channel: &Sender<RelayCommand>, command: RelayCommand) {
+    if let Err(e) = channel.try_send(command) {
+        gloo::console::error!("Error sending relay command: ", format!("{:?}", e));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nostro2::{
+        notes::{Note, SignedNote},
+        relays::RelayEvents,
+        userkeys::UserKeys,
+    };
+    use yew::prelude::*;
+
+    use super::{RelayPool, Shared};
+    use crate::relay_pool::{
+        buffer::RingBuffer, replaceable::ReplaceableIndex, subscription::SubscriptionFeed,
+        verify::NoteVerification,
+    };
+
+    fn pool() -> RelayPool {
+        RelayPool {
+            relay_events: RingBuffer::new(10),
+            new_notes: RingBuffer::new(10),
+            unique_ids: Default::default(),
+            verification: NoteVerification::Flag,
+            flagged_notes: Shared::default(),
+            replaceable: ReplaceableIndex::default(),
+            outbox: Shared::default(),
+            flush_outbox_callback: Callback::noop(),
+            dismiss_outbox_callback: Callback::noop(),
+            _outbox_listener: None,
+            cache_relay: false,
+            pending_cache: Vec::new(),
+            cached_notes_callback: Callback::noop(),
+            flush_cache_callback: Callback::noop(),
+            relay_status: Shared::default(),
+            relay_channels: Vec::new(),
+            subscriptions: Vec::new(),
+            subscription_feeds: Shared::default(),
+            pending_publishes: Default::default(),
+            relay_update_callback: Callback::noop(),
+            send_note_callback: Callback::noop(),
+            publish_callback: Callback::noop(),
+            subscribe_callback: Callback::noop(),
+            send_note_to_callback: Callback::noop(),
+            subscribe_to_callback: Callback::noop(),
+            unsubscribe_callback: Callback::noop(),
+            add_relay_callback: Callback::noop(),
+            remove_relay_callback: Callback::noop(),
+            close_callback: Callback::noop(),
+            children: Children::default(),
+        }
+    }
+
+    fn forged(note: &SignedNote, content: &str) -> SignedNote {
+        let mut json = serde_json::to_value(note).unwrap();
+        json["content"] = serde_json::Value::from(content);
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn event(note: &SignedNote) -> RelayEvents {
+        RelayEvents::EVENT("sub".to_string(), note.clone())
+    }
+
+    #[test]
+    fn forged_copy_does_not_taint_the_genuine_note() {
+        let keys = UserKeys::generate();
+        let genuine = keys.sign_nostr_event(Note::new(&keys.get_public_key(), 1, "genuine"));
+        let forgery = forged(&genuine, "forged");
+
+        let mut pool = pool();
+        pool.ingest("wss://bad", event(&forgery), false);
+        assert!(pool.flagged_notes.contains(genuine.get_id()));
+        pool.ingest("wss://good", event(&genuine), true);
+        assert!(pool.flagged_notes.is_empty());
+        assert_eq!(
+            pool.new_notes.snapshot().iter().collect::<Vec<_>>(),
+            vec![&genuine]
+        );
+
+        pool.ingest("wss://bad", event(&forgery), false);
+        assert!(pool.flagged_notes.is_empty());
+        assert_eq!(pool.new_notes.len(), 1);
+    }
+
+    #[test]
+    fn flagged_notes_are_not_archived() {
+        let keys = UserKeys::generate();
+        let genuine = keys.sign_nostr_event(Note::new(&keys.get_public_key(), 1, "genuine"));
+        let other = keys.sign_nostr_event(Note::new(&keys.get_public_key(), 1, "other"));
+        let forgery = forged(&other, "forged");
+
+        let mut pool = pool();
+        pool.ingest("wss://good", event(&genuine), true);
+        pool.ingest("wss://bad", event(&forgery), false);
+        let evicted = pool.new_notes.set_capacity(0);
+        assert_eq!(evicted.len(), 2);
+        assert_eq!(pool.release_notes(evicted), vec![genuine]);
+        assert!(pool.flagged_notes.is_empty());
+        assert!(pool.unique_ids.is_empty());
+    }
+
+    #[test]
+    fn forged_replacement_keeps_the_genuine_version() {
+        let keys = UserKeys::generate();
+        let mut profile = Note::new(&keys.get_public_key(), 0, "genuine");
+        profile.created_at = 10;
+        let profile = keys.sign_nostr_event(profile);
+        let mut newer = Note::new(&keys.get_public_key(), 0, "newer");
+        newer.created_at = 20;
+        let forgery = forged(&keys.sign_nostr_event(newer), "forged");
+
+        let mut pool = pool();
+        pool.ingest("wss://good", event(&profile), true);
+        pool.ingest("wss://bad", event(&forgery), false);
+        assert_eq!(
+            pool.replaceable.latest(&keys.get_public_key(), 0, None),
+            Some(&profile)
+        );
+        assert!(pool.new_notes.snapshot().contains(&profile));
+        assert!(pool.flagged_notes.contains(forgery.get_id()));
+    }
+
+    #[test]
+    fn replacement_reaches_every_feed_holding_the_old_version() {
+        let keys = UserKeys::generate();
+        let mut profile = Note::new(&keys.get_public_key(), 0, "old");
+        profile.created_at = 10;
+        let profile = keys.sign_nostr_event(profile);
+        let mut newer = Note::new(&keys.get_public_key(), 0, "new");
+        newer.created_at = 20;
+        let newer = keys.sign_nostr_event(newer);
+
+        let mut pool = pool();
+        for id in ["a", "b"] {
+            pool.subscription_feeds
+                .make_mut()
+                .insert(id.to_string(), Shared::new(SubscriptionFeed::new([], 10)));
+        }
+        pool.ingest(
+            "wss://one",
+            RelayEvents::EVENT("a".to_string(), profile),
+            true,
+        );
+        pool.ingest(
+            "wss://two",
+            RelayEvents::EVENT("b".to_string(), newer.clone()),
+            true,
+        );
+        for id in ["a", "b"] {
+            let notes = pool.subscription_feeds[id].notes();
+            assert_eq!(notes.iter().collect::<Vec<_>>(), vec![&newer]);
+        }
     }
 }
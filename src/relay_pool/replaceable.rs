@@ -0,0 +1,134 @@
+use std::{collections::HashMap, rc::Rc};
+
+use nostro2::notes::SignedNote;
+
+// NIP-01 addresses: `kind:pubkey:` for replaceable kinds and `kind:pubkey:d-tag` for
+// parameterized replaceable ones; every other kind keeps all of its versions.
+pub fn note_address(note: &SignedNote) -> Option<String> {
+    let d_tag = match note.get_kind() {
+        0 | 3 | 10_000..=19_999 => String::new(),
+        30_000..=39_999 => note
+            .get_tags_by_id("d")
+            .and_then(|values| values.into_iter().next())
+            .unwrap_or_default(),
+        _ => return None,
+    };
+    Some(format!(
+        "{}:{}:{}",
+        note.get_kind(),
+        note.get_pubkey(),
+        d_tag
+    ))
+}
+
+// Ties on created_at keep the lowest id, as NIP-01 asks.
+pub fn supersedes(candidate: &SignedNote, current: &SignedNote) -> bool {
+    match candidate.get_created_at().cmp(&current.get_created_at()) {
+        std::cmp::Ordering::Greater => true,
+        std::cmp::Ordering::Less => false,
+        std::cmp::Ordering::Equal => candidate.get_id() < current.get_id(),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ReplaceableOffer {
+    NotReplaceable,
+    Stale,
+    Current(Option<SignedNote>),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ReplaceableIndex {
+    current: Rc<HashMap<String, SignedNote>>,
+}
+impl PartialEq for ReplaceableIndex {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.current, &other.current)
+    }
+}
+impl ReplaceableIndex {
+    pub fn offer(&mut self, note: &SignedNote) -> ReplaceableOffer {
+        let Some(address) = note_address(note) else {
+            return ReplaceableOffer::NotReplaceable;
+        };
+        if let Some(current) = self.current.get(&address) {
+            if current.get_id() == note.get_id() {
+                return ReplaceableOffer::Current(None);
+            }
+            if !supersedes(note, current) {
+                return ReplaceableOffer::Stale;
+            }
+        }
+        ReplaceableOffer::Current(Rc::make_mut(&mut self.current).insert(address, note.clone()))
+    }
+    // The index only tracks notes the pool still holds, so it is bounded the same way.
+    pub fn forget(&mut self, note: &SignedNote) {
+        let Some(address) = note_address(note) else {
+            return;
+        };
+        let current = self.current.get(&address);
+        if current.is_some_and(|current| current.get_id() == note.get_id()) {
+            Rc::make_mut(&mut self.current).remove(&address);
+        }
+    }
+    pub fn get(&self, address: &str) -> Option<&SignedNote> {
+        self.current.get(address)
+    }
+    pub fn latest(&self, pubkey: &str, kind: u32, d_tag: Option<&str>) -> Option<&SignedNote> {
+        self.get(&format!(
+            "{}:{}:{}",
+            kind,
+            pubkey,
+            d_tag.unwrap_or_default()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nostro2::{
+        notes::{Note, SignedNote},
+        userkeys::UserKeys,
+    };
+
+    use super::{note_address, ReplaceableIndex, ReplaceableOffer};
+
+    fn signed(keys: &UserKeys, kind: u32, created_at: u64, d_tag: Option<&str>) -> SignedNote {
+        let mut note = Note::new(&keys.get_public_key(), kind, "");
+        note.created_at = created_at;
+        if let Some(d_tag) = d_tag {
+            note.add_tag("d", d_tag);
+        }
+        keys.sign_nostr_event(note)
+    }
+
+    #[test]
+    fn index_keeps_newest_version_per_address() {
+        let keys = UserKeys::generate();
+        let pubkey = keys.get_public_key();
+        let mut index = ReplaceableIndex::default();
+        let old_profile = signed(&keys, 0, 10, None);
+        let new_profile = signed(&keys, 0, 20, None);
+        assert_eq!(index.offer(&old_profile), ReplaceableOffer::Current(None));
+        assert_eq!(
+            index.offer(&new_profile),
+            ReplaceableOffer::Current(Some(old_profile.clone()))
+        );
+        assert_eq!(index.offer(&old_profile), ReplaceableOffer::Stale);
+        assert_eq!(index.latest(&pubkey, 0, None), Some(&new_profile));
+        index.forget(&old_profile);
+        assert_eq!(index.latest(&pubkey, 0, None), Some(&new_profile));
+        index.forget(&new_profile);
+        assert_eq!(index.latest(&pubkey, 0, None), None);
+
+        let settings = signed(&keys, 30078, 5, Some("settings"));
+        let drafts = signed(&keys, 30078, 5, Some("drafts"));
+        assert_ne!(note_address(&settings), note_address(&drafts));
+        assert_eq!(index.offer(&settings), ReplaceableOffer::Current(None));
+        assert_eq!(index.offer(&drafts), ReplaceableOffer::Current(None));
+        assert_eq!(
+            index.offer(&signed(&keys, 1, 5, None)),
+            ReplaceableOffer::NotReplaceable
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use std::{collections::HashSet, rc::Rc};
+
+use im_rc::HashSet as SharedSet;
+use nostro2::{notes::SignedNote, relays::NostrSubscription};
+use yew::prelude::*;
+
+use super::buffer::{BufferSnapshot, RingBuffer};
+use super::relay_pool::NostrProps;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubscriptionFeed {
+    notes: RingBuffer<SignedNote>,
+    note_ids: SharedSet<String>,
+    relays: HashSet<String>,
+    eose_relays: HashSet<String>,
+}
+impl SubscriptionFeed {
+    pub fn new(relays: impl IntoIterator<Item = String>, capacity: usize) -> Self {
+        Self {
+            notes: RingBuffer::new(capacity),
+            note_ids: SharedSet::new(),
+            relays: relays.into_iter().collect(),
+            eose_relays: HashSet::new(),
+        }
+    }
+    pub fn notes(&self) -> BufferSnapshot<SignedNote> {
+        self.notes.snapshot()
+    }
+    // Replayed subscriptions after a reconnect resend stored events, so ids are
+    // deduplicated per feed. The id set is persistent so copying a feed that a
+    // consumer still holds stays cheap.
+    pub fn push(&mut self, note: SignedNote) -> bool {
+        if self.note_ids.insert(note.get_id().to_string()).is_some() {
+            return false;
+        }
+        for evicted in self.notes.push(note) {
+            self.note_ids.remove(evicted.get_id());
+        }
+        true
+    }
+    pub fn contains(&self, note_id: &str) -> bool {
+        self.note_ids.contains(note_id)
+    }
+    pub fn remove(&mut self, note_id: &str) {
+        if self.note_ids.remove(note_id).is_some() {
+            self.notes.retain(|note| note.get_id() != note_id);
+        }
+    }
+    pub fn mark_eose(&mut self, url: &str) {
+        if self.relays.contains(url) {
+            self.eose_relays.insert(url.to_string());
+        }
+    }
+    pub fn is_eose(&self) -> bool {
+        self.relays.iter().all(|url| self.eose_relays.contains(url))
+    }
+    pub fn add_relay(&mut self, url: String) {
+        self.relays.insert(url);
+    }
+    pub fn remove_relay(&mut self, url: &str) {
+        self.relays.remove(url);
+        self.eose_relays.remove(url);
+    }
+}
+
+struct HandleInner {
+    id: String,
+    unsubscribe: Callback<String>,
+}
+impl Drop for HandleInner {
+    fn drop(&mut self) {
+        self.unsubscribe.emit(self.id.clone());
+    }
+}
+
+// Closes the subscription on every relay once the last clone is dropped.
+#[derive(Clone)]
+pub struct SubscriptionHandle(Rc<HandleInner>);
+impl SubscriptionHandle {
+    pub fn id(&self) -> &str {
+        &self.0.id
+    }
+    pub fn feed<'a>(&self, props: &'a NostrProps) -> Option<&'a SubscriptionFeed> {
+        props.subscription_feeds.get(self.id()).map(|feed| &**feed)
+    }
+    pub fn notes(&self, props: &NostrProps) -> BufferSnapshot<SignedNote> {
+        self.feed(props)
+            .map(SubscriptionFeed::notes)
+            .unwrap_or_default()
+    }
+    pub fn is_eose(&self, props: &NostrProps) -> bool {
+        self.feed(props).is_some_and(SubscriptionFeed::is_eose)
+    }
+}
+impl PartialEq for SubscriptionHandle {
+    fn eq(&self, other: &Self) -> bool {
+        self.id() == other.id()
+    }
+}
+
+impl NostrProps {
+    pub fn subscribe_handle(&self, subscription: NostrSubscription) -> SubscriptionHandle {
+        let id = subscription.id();
+        self.subscribe.emit(subscription);
+        SubscriptionHandle(Rc::new(HandleInner {
+            id,
+            unsubscribe: self.unsubscribe.clone(),
+        }))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct UseNostrSubscription {
+    pub id: Option<String>,
+    pub notes: BufferSnapshot<SignedNote>,
+    pub eose: bool,
+}
+
+#[hook]
+pub fn use_nostr_subscription<D, F>(deps: D, subscription: F) -> UseNostrSubscription
+where
+    D: Clone + PartialEq + 'static,
+    F: FnOnce(&D) -> NostrSubscription + 'static,
+{
+    let context = use_context::<NostrProps>();
+    let id = use_state(|| None::<String>);
+    {
+        let context = context.clone();
+        let id = id.clone();
+        use_effect_with(deps, move |deps| {
+            let handle = context.map(|context| context.subscribe_handle(subscription(deps)));
+            id.set(handle.as_ref().map(|handle| handle.id().to_string()));
+            move || drop(handle)
+        });
+    }
+    let feed = context
+        .as_ref()
+        .zip(id.as_ref())
+        .and_then(|(context, id)| context.subscription_feeds.get(id))
+        .map(|feed| &**feed);
+    UseNostrSubscription {
+        id: (*id).clone(),
+        notes: feed.map(SubscriptionFeed::notes).unwrap_or_default(),
+        eose: feed.is_some_and(SubscriptionFeed::is_eose),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriptionFeed;
+
+    #[test]
+    fn feed_reaches_eose_once_every_relay_answered() {
+        let mut feed = SubscriptionFeed::new(["wss://a".to_string(), "wss://b".to_string()], 10);
+        feed.mark_eose("wss://a");
+        feed.mark_eose("wss://unknown");
+        assert!(!feed.is_eose());
+        feed.remove_relay("wss://b");
+        assert!(feed.is_eose());
+        feed.add_relay("wss://c".to_string());
+        assert!(!feed.is_eose());
+    }
+}
@@ -0,0 +1,47 @@
+use nostro2::notes::SignedNote;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum NoteVerification {
+    #[default]
+    Drop,
+    Flag,
+}
+
+fn is_hex(value: &str, bytes: usize) -> bool {
+    value.len() == bytes * 2 && value.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+// nostro2 panics on malformed hex while checking signatures, so field shapes are
+// checked before handing a relay's note to it.
+pub fn verify_note(note: &SignedNote) -> bool {
+    is_hex(note.get_id(), 32)
+        && is_hex(note.get_pubkey(), 32)
+        && is_hex(note.get_sig(), 64)
+        && note.verify()
+}
+
+#[cfg(test)]
+mod tests {
+    use nostro2::{
+        notes::{Note, SignedNote},
+        userkeys::UserKeys,
+    };
+
+    use super::verify_note;
+
+    fn tampered(note: &SignedNote, field: &str, value: &str) -> SignedNote {
+        let mut json = serde_json::to_value(note).unwrap();
+        json[field] = serde_json::Value::from(value);
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn verify_note_rejects_forged_and_malformed_notes() {
+        let keys = UserKeys::generate();
+        let note = keys.sign_nostr_event(Note::new(&keys.get_public_key(), 1, "hello"));
+        assert!(verify_note(&note));
+        assert!(!verify_note(&tampered(&note, "content", "forged")));
+        assert!(!verify_note(&tampered(&note, "sig", "not hex")));
+        assert!(!verify_note(&tampered(&note, "id", &"zz".repeat(32))));
+    }
+}